@@ -0,0 +1,141 @@
+#ifndef MBO_ORDERBOOK_H
+#define MBO_ORDERBOOK_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Price of a missing level.
+#define MBO_UNDEF_PRICE INT64_MAX
+
+typedef enum MboStatus {
+  MBO_STATUS_OK = 0,
+  // No such book, level or order; the output was left unchanged or zeroed.
+  MBO_STATUS_NOT_FOUND = 1,
+  MBO_STATUS_NULL_POINTER = -1,
+  // The bytes are neither a DBN stream nor whole DBN records.
+  MBO_STATUS_INVALID_RECORD = -2,
+  MBO_STATUS_UNKNOWN_PUBLISHER = -3,
+  // A record was inconsistent with the book, e.g. a duplicate add. The market shouldn't be
+  // used any further.
+  MBO_STATUS_BOOK_ERROR = -4,
+} MboStatus;
+
+// Opaque handle to a `Market`.
+typedef struct MboMarket MboMarket;
+
+typedef struct MboPriceLevel {
+  int64_t price;
+  uint32_t size;
+  uint32_t count;
+} MboPriceLevel;
+
+// Same layout as DBN's `BidAskPair`.
+typedef struct MboBidAskPair {
+  int64_t bid_px;
+  int64_t ask_px;
+  uint32_t bid_sz;
+  uint32_t ask_sz;
+  uint32_t bid_ct;
+  uint32_t ask_ct;
+} MboBidAskPair;
+
+// A resting order as last added or modified.
+typedef struct MboOrder {
+  uint64_t ts_event;
+  uint64_t ts_recv;
+  uint64_t order_id;
+  int64_t price;
+  uint32_t size;
+  uint32_t instrument_id;
+  uint32_t sequence;
+  uint16_t publisher_id;
+  // `'B'` or `'A'`.
+  uint8_t side;
+  uint8_t flags;
+} MboOrder;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create an empty market. Free it with `mbo_market_free`.
+struct MboMarket *mbo_market_new(void);
+
+// Free a market created by `mbo_market_new`. `market` may be null.
+//
+// # Safety
+// `market` must come from `mbo_market_new` and not have been freed.
+void mbo_market_free(struct MboMarket *market);
+
+// Apply the MBO records in `data`: either a DBN stream with its metadata or concatenated raw
+// DBN records. Other record types are skipped. The number of records applied is written to
+// `applied` unless it's null.
+//
+// # Safety
+// `market` must be a live market and `data` point to `len` readable bytes.
+enum MboStatus mbo_market_apply(struct MboMarket *market,
+                                const uint8_t *data,
+                                size_t len,
+                                size_t *applied);
+
+// Best bid and offer of one book.
+//
+// # Safety
+// `market` must be a live market; `bid` and `ask` must be writable.
+enum MboStatus mbo_market_bbo(const struct MboMarket *market,
+                              uint32_t instrument_id,
+                              uint16_t publisher_id,
+                              struct MboPriceLevel *bid,
+                              struct MboPriceLevel *ask);
+
+// Best bid and offer across every publisher's book for the instrument.
+//
+// # Safety
+// `market` must be a live market; `bid` and `ask` must be writable.
+enum MboStatus mbo_market_aggregated_bbo(const struct MboMarket *market,
+                                         uint32_t instrument_id,
+                                         struct MboPriceLevel *bid,
+                                         struct MboPriceLevel *ask);
+
+// Top `level_count` levels of one book, best first, into `levels`. The number of levels
+// with a bid or an ask is written to `filled` unless it's null.
+//
+// # Safety
+// `market` must be a live market and `levels` point to `level_count` writable pairs.
+enum MboStatus mbo_market_depth(const struct MboMarket *market,
+                                uint32_t instrument_id,
+                                uint16_t publisher_id,
+                                struct MboBidAskPair *levels,
+                                size_t level_count,
+                                size_t *filled);
+
+// Look up a resting order.
+//
+// # Safety
+// `market` must be a live market and `order` writable.
+enum MboStatus mbo_market_order(const struct MboMarket *market,
+                                uint32_t instrument_id,
+                                uint16_t publisher_id,
+                                uint64_t order_id,
+                                struct MboOrder *order);
+
+// Size resting ahead of an order at its price level.
+//
+// # Safety
+// `market` must be a live market and `queue_pos` writable.
+enum MboStatus mbo_market_queue_pos(const struct MboMarket *market,
+                                    uint32_t instrument_id,
+                                    uint16_t publisher_id,
+                                    uint64_t order_id,
+                                    uint32_t *queue_pos);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MBO_ORDERBOOK_H */
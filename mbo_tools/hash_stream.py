@@ -0,0 +1,35 @@
+"""Print the book state hash stream of a DBN file, in the same format as
+`mbo-replay --quiet --hash-every N`, so the Python and Rust books can be diffed:
+
+    python mbo_tools/hash_stream.py CLX5_mbo.dbn --every 1000 > py.txt
+    cargo run --bin mbo-replay -- CLX5_mbo.dbn --quiet --hash-every 1000 > rs.txt
+    diff py.txt rs.txt
+"""
+
+import argparse
+
+import databento as db
+
+from order_book import Market
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("path", help="Path to the input DBN file")
+    parser.add_argument("--every", type=int, default=1, help="Print every N records")
+    args = parser.parse_args()
+
+    market = Market()
+    every = max(args.every, 1)
+    for idx, mbo in enumerate(db.DBNStore.from_file(args.path), start=1):
+        market.apply(mbo)
+        if idx % every == 0:
+            book = market.get_book(mbo.instrument_id, mbo.publisher_id)
+            print(
+                f"{idx} {mbo.ts_recv} {mbo.instrument_id} {mbo.publisher_id} "
+                f"{book.state_hash():016x}"
+            )
+
+
+if __name__ == "__main__":
+    main()
@@ -8,6 +8,18 @@ from itertools import takewhile
 import databento as db
 from sortedcontainers import SortedDict
 
+# 64-bit FNV-1a, matching `Book::state_hash` in the Rust crate
+FNV_OFFSET = 0xCBF29CE484222325
+FNV_PRIME = 0x100000001B3
+U64_MASK = (1 << 64) - 1
+
+
+def fnv1a(hash: int, data: bytes) -> int:
+    for byte in data:
+        hash ^= byte
+        hash = (hash * FNV_PRIME) & U64_MASK
+    return hash
+
 
 @dataclass(slots=True)
 class PriceLevel:
@@ -36,6 +48,12 @@ class LevelOrders:
             size=sum(o.size for o in self.orders),
         )
 
+    def state_hash(self, side: str) -> int:
+        hash = fnv1a(FNV_OFFSET, side.encode() + self.price.to_bytes(8, "little", signed=True))
+        for o in self.orders:
+            hash = fnv1a(hash, o.order_id.to_bytes(8, "little") + o.size.to_bytes(4, "little"))
+        return hash
+
     def to_dict(self):
         return {
             "price": self.price,
@@ -117,6 +135,14 @@ class Book:
             snapshots.append(ba_pair)
         return snapshots
 
+    def state_hash(self) -> int:
+        """Hash of the resting orders in priority order, identical to the Rust `Book::state_hash`."""
+        hash = 0
+        for side, levels in (("B", self.bids), ("A", self.offers)):
+            for level in levels.values():
+                hash = (hash + level.state_hash(side)) & U64_MASK
+        return hash
+
     def apply(self, mbo: db.MBOMsg) -> None:
         # Trade, Fill, or None: no change
         if mbo.action in ("T", "F", "N"):
@@ -0,0 +1,117 @@
+"""Replay a DBN file through the Python and Rust order books and diff them at checkpoints.
+
+The Rust side is `mbo-dump`, which writes `Market.to_dict(include_orders=True)`-shaped JSON:
+
+    python mbo_tools/parity.py CLX5_mbo.dbn --every 10000
+    python mbo_tools/parity.py CLX5_mbo.dbn --at 100,2500 --ignore action,flags
+"""
+
+import argparse
+import contextlib
+import io
+import json
+import shlex
+import subprocess
+import sys
+
+import databento as db
+
+from order_book import Market
+
+RUST_CMD = "cargo run --release -q --bin mbo-dump --"
+
+
+def python_checkpoints(path: str, every: int, at: set[int]) -> dict[int, dict]:
+    market = Market()
+    checkpoints = {}
+
+    def dump() -> dict:
+        # `to_dict` prints the books as it goes
+        with contextlib.redirect_stdout(io.StringIO()):
+            out = market.to_dict(include_orders=True)
+        return json.loads(json.dumps(out))
+
+    idx = 0
+    for idx, mbo in enumerate(db.DBNStore.from_file(path), start=1):
+        market.apply(mbo)
+        if (every > 0 and idx % every == 0) or idx in at:
+            checkpoints[idx] = dump()
+    if idx not in checkpoints:
+        checkpoints[idx] = dump()
+    return checkpoints
+
+
+def rust_checkpoints(path: str, every: int, at: set[int], rust_cmd: str) -> dict[int, dict]:
+    cmd = shlex.split(rust_cmd) + [path, "--every", str(every)]
+    if at:
+        cmd += ["--at", ",".join(str(i) for i in sorted(at))]
+    out = subprocess.run(cmd, check=True, capture_output=True, text=True).stdout
+    checkpoints = {}
+    for line in out.splitlines():
+        checkpoint = json.loads(line)
+        checkpoints[checkpoint["record"]] = checkpoint["market"]
+    return checkpoints
+
+
+def diff(py, rs, ignore: set[str], path: str = "") -> list[tuple[str, object, object]]:
+    if isinstance(py, dict) and isinstance(rs, dict):
+        out = []
+        for key in sorted(set(py) | set(rs)):
+            if key in ignore:
+                continue
+            if key not in py or key not in rs:
+                out.append((f"{path}/{key}", py.get(key), rs.get(key)))
+            else:
+                out += diff(py[key], rs[key], ignore, f"{path}/{key}")
+        return out
+    if isinstance(py, list) and isinstance(rs, list):
+        out = []
+        for idx in range(max(len(py), len(rs))):
+            if idx >= len(py) or idx >= len(rs):
+                out.append((f"{path}[{idx}]", py[idx] if idx < len(py) else None,
+                            rs[idx] if idx < len(rs) else None))
+            else:
+                out += diff(py[idx], rs[idx], ignore, f"{path}[{idx}]")
+        return out
+    return [] if py == rs else [(path, py, rs)]
+
+
+def main() -> int:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("path", help="Path to the input DBN file")
+    parser.add_argument("--every", type=int, default=0, help="Compare every N records")
+    parser.add_argument("--at", default="", help="Also compare after these records, e.g. 10,250")
+    parser.add_argument("--ignore", default="", help="Order fields to ignore, e.g. action,flags")
+    parser.add_argument("--max-diffs", type=int, default=20, help="Differences to print")
+    parser.add_argument("--rust-cmd", default=RUST_CMD, help="Command running mbo-dump")
+    args = parser.parse_args()
+
+    at = {int(i) for i in args.at.split(",") if i}
+    ignore = {f for f in args.ignore.split(",") if f}
+    rust = rust_checkpoints(args.path, args.every, at, args.rust_cmd)
+    python = python_checkpoints(args.path, args.every, at)
+
+    printed = 0
+    diverged = []
+    for record in sorted(set(python) | set(rust)):
+        if record not in python or record not in rust:
+            print(f"record {record}: checkpoint missing from "
+                  f"{'python' if record not in python else 'rust'}")
+            diverged.append(record)
+            continue
+        diffs = diff(python[record], rust[record], ignore)
+        if diffs:
+            diverged.append(record)
+        for path, py, rs in diffs:
+            if printed < args.max_diffs:
+                print(f"record {record}: {path}: python={py!r} rust={rs!r}")
+                printed += 1
+
+    print(f"{len(python)} checkpoints compared, {len(diverged)} diverged", file=sys.stderr)
+    if diverged:
+        print(f"first divergence after record {diverged[0]}", file=sys.stderr)
+    return 1 if diverged else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
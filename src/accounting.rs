@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use databento::dbn::{Metadata, Publisher, Side};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backtest::Fill,
+    common::{instrument_symbols, to_px},
+    orderbook::Market,
+};
+
+/// Fee per unit traded; negative values are rebates.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct FeeRate {
+    #[serde(default)]
+    pub maker: f64,
+    #[serde(default)]
+    pub taker: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FeeSchedule {
+    #[serde(default)]
+    pub default: FeeRate,
+    #[serde(default)]
+    pub publishers: HashMap<Publisher, FeeRate>,
+}
+
+/// Which price open positions are marked to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkPrice {
+    /// Aggregated BBO mid, falling back to the last trade when a side is empty.
+    #[default]
+    Mid,
+    /// Last trade, falling back to the aggregated BBO mid before the first trade.
+    LastTrade,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AccountingConfig {
+    #[serde(default)]
+    pub fees: FeeSchedule,
+    /// Contract multipliers by raw symbol or futures root, e.g. `"CL": 1000`.
+    #[serde(default)]
+    pub multipliers: HashMap<String, f64>,
+    #[serde(default)]
+    pub mark: MarkPrice,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Position {
+    pub qty: i64,
+    pub avg_px: f64,
+    pub realized_pnl: f64,
+    pub fees: f64,
+    pub volume: u64,
+}
+
+/// Positions, PnL and fees per instrument.
+#[derive(Debug, Clone, Default)]
+pub struct Portfolio {
+    config: AccountingConfig,
+    positions: HashMap<u32, Position>,
+    symbols: HashMap<u32, String>,
+    last_trade: HashMap<u32, i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionReport {
+    pub instrument_id: u32,
+    pub symbol: Option<String>,
+    pub qty: i64,
+    pub avg_px: f64,
+    pub mark_px: Option<f64>,
+    pub multiplier: f64,
+    pub volume: u64,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub fees: f64,
+    pub net_pnl: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountingReport {
+    pub positions: Vec<PositionReport>,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub fees: f64,
+    pub net_pnl: f64,
+}
+
+impl FeeSchedule {
+    pub fn rate(&self, publisher: Publisher) -> FeeRate {
+        self.publishers
+            .get(&publisher)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+impl AccountingConfig {
+    pub fn from_json_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn multiplier(&self, symbol: &str) -> f64 {
+        self.multipliers
+            .get(symbol)
+            .or_else(|| futures_root(symbol).and_then(|root| self.multipliers.get(root)))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+impl Position {
+    /// Apply a fill of `size` at `px` (in price units, not fixed precision).
+    pub fn apply(&mut self, side: Side, px: f64, size: u32, multiplier: f64) {
+        let qty = match side {
+            Side::Bid => size as i64,
+            Side::Ask => -(size as i64),
+            Side::None => return,
+        };
+        self.volume += size as u64;
+        if self.qty == 0 || self.qty.signum() == qty.signum() {
+            // Opening or increasing: blend the average price
+            let new_qty = self.qty + qty;
+            self.avg_px = (self.avg_px * self.qty as f64 + px * qty as f64) / new_qty as f64;
+            self.qty = new_qty;
+            return;
+        }
+        // Reducing, closing or flipping
+        let closed = qty.abs().min(self.qty.abs());
+        self.realized_pnl += (px - self.avg_px) * (closed * self.qty.signum()) as f64 * multiplier;
+        self.qty += qty;
+        if self.qty == 0 {
+            self.avg_px = 0.0;
+        } else if self.qty.signum() == qty.signum() {
+            self.avg_px = px;
+        }
+    }
+
+    pub fn unrealized_pnl(&self, mark_px: f64, multiplier: f64) -> f64 {
+        (mark_px - self.avg_px) * self.qty as f64 * multiplier
+    }
+}
+
+impl Portfolio {
+    pub fn new(config: AccountingConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Pick up raw symbols so multipliers configured by symbol apply.
+    pub fn load_symbology(&mut self, metadata: &Metadata) {
+        self.symbols.extend(instrument_symbols(metadata));
+    }
+
+    pub fn set_symbol(&mut self, instrument_id: u32, symbol: impl Into<String>) {
+        self.symbols.insert(instrument_id, symbol.into());
+    }
+
+    pub fn position(&self, instrument_id: u32) -> Option<&Position> {
+        self.positions.get(&instrument_id)
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = (&u32, &Position)> {
+        self.positions.iter()
+    }
+
+    pub fn multiplier(&self, instrument_id: u32) -> f64 {
+        self.symbols
+            .get(&instrument_id)
+            .map_or(1.0, |symbol| self.config.multiplier(symbol))
+    }
+
+    pub fn apply_fill(&mut self, fill: &Fill) {
+        let multiplier = self.multiplier(fill.instrument_id);
+        let rate = self.config.fees.rate(fill.publisher);
+        let fee = if fill.aggressive {
+            rate.taker
+        } else {
+            rate.maker
+        };
+        let position = self.positions.entry(fill.instrument_id).or_default();
+        position.apply(fill.side, to_px(fill.price), fill.size, multiplier);
+        position.fees += fill.size as f64 * fee;
+    }
+
+    pub fn on_trade(&mut self, instrument_id: u32, price: i64) {
+        self.last_trade.insert(instrument_id, price);
+    }
+
+    pub fn mark_px(&self, market: &Market, instrument_id: u32) -> Option<f64> {
+        let mid = || match market.aggregated_bbo(instrument_id) {
+            (Some(bid), Some(ask)) => Some((to_px(bid.price) + to_px(ask.price)) / 2.0),
+            _ => None,
+        };
+        let last = || self.last_trade.get(&instrument_id).copied().map(to_px);
+        match self.config.mark {
+            MarkPrice::Mid => mid().or_else(last),
+            MarkPrice::LastTrade => last().or_else(mid),
+        }
+    }
+
+    pub fn report(&self, market: &Market) -> AccountingReport {
+        let mut positions: Vec<PositionReport> = self
+            .positions
+            .iter()
+            .map(|(instrument_id, position)| {
+                let multiplier = self.multiplier(*instrument_id);
+                let mark_px = self.mark_px(market, *instrument_id);
+                let unrealized_pnl =
+                    mark_px.map_or(0.0, |mark_px| position.unrealized_pnl(mark_px, multiplier));
+                PositionReport {
+                    instrument_id: *instrument_id,
+                    symbol: self.symbols.get(instrument_id).cloned(),
+                    qty: position.qty,
+                    avg_px: position.avg_px,
+                    mark_px,
+                    multiplier,
+                    volume: position.volume,
+                    realized_pnl: position.realized_pnl,
+                    unrealized_pnl,
+                    fees: position.fees,
+                    net_pnl: position.realized_pnl + unrealized_pnl - position.fees,
+                }
+            })
+            .collect();
+        positions.sort_by_key(|position| position.instrument_id);
+        AccountingReport {
+            realized_pnl: positions.iter().map(|p| p.realized_pnl).sum(),
+            unrealized_pnl: positions.iter().map(|p| p.unrealized_pnl).sum(),
+            fees: positions.iter().map(|p| p.fees).sum(),
+            net_pnl: positions.iter().map(|p| p.net_pnl).sum(),
+            positions,
+        }
+    }
+}
+
+impl AccountingReport {
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// One row per instrument.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "instrument_id,symbol,qty,avg_px,mark_px,multiplier,volume,realized_pnl,unrealized_pnl,fees,net_pnl"
+        )?;
+        for p in &self.positions {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                p.instrument_id,
+                p.symbol.as_deref().unwrap_or_default(),
+                p.qty,
+                p.avg_px,
+                p.mark_px.map(|px| px.to_string()).unwrap_or_default(),
+                p.multiplier,
+                p.volume,
+                p.realized_pnl,
+                p.unrealized_pnl,
+                p.fees,
+                p.net_pnl,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write JSON or CSV depending on the file extension.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        if path.extension().is_some_and(|ext| ext == "csv") {
+            self.write_csv(file)
+        } else {
+            self.write_json(file)
+        }
+    }
+}
+
+/// Strip a futures month code and year, e.g. `CLX5` or `CLZ25` -> `CL`.
+fn futures_root(symbol: &str) -> Option<&str> {
+    let digits = symbol.len() - symbol.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 || digits > 2 {
+        return None;
+    }
+    let rest = &symbol[..symbol.len() - digits];
+    let root = rest.strip_suffix(|c: char| "FGHJKMNQUVXZ".contains(c))?;
+    (!root.is_empty()).then_some(root)
+}
@@ -0,0 +1,702 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use databento::dbn::{decode::DbnMetadata, Action, MboMsg, Publisher, Record, Side};
+
+use crate::{
+    accounting::{AccountingConfig, AccountingReport, Portfolio, Position},
+    compression::file_decoder,
+    latency::{LatencyConfig, LatencyModel},
+    orderbook::{Book, Market},
+};
+
+/// User trading logic driven by a [`Backtest`].
+///
+/// All callbacks have empty default implementations so a strategy only
+/// overrides what it needs.
+pub trait Strategy {
+    /// Called after the last record of a book-changing event (`F_LAST`) was applied.
+    fn on_book_update(&mut self, _ctx: &mut Context<'_>, _mbo: &MboMsg) {}
+
+    /// Called for every trade print, after any simulated fills it caused.
+    fn on_trade(&mut self, _ctx: &mut Context<'_>, _trade: &MboMsg) {}
+
+    /// Called when a timer scheduled with [`Context::schedule_timer`] expires.
+    fn on_timer(&mut self, _ctx: &mut Context<'_>, _ts: u64) {}
+
+    /// Called for every simulated fill of one of the strategy's orders.
+    fn on_fill(&mut self, _ctx: &mut Context<'_>, _fill: &Fill) {}
+}
+
+/// Handle passed to strategy callbacks to inspect the market and trade.
+///
+/// `market()` is the book as seen by the strategy, i.e. delayed by the
+/// market-data latency, while orders and cancels only reach the simulated
+/// exchange after the order-entry latency.
+pub struct Context<'a> {
+    now: u64,
+    market: &'a Market,
+    exchange: &'a SimExchange,
+    portfolio: &'a Portfolio,
+    pending: &'a mut Pending,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderRequest {
+    pub id: u64,
+    pub instrument_id: u32,
+    pub publisher: Publisher,
+    pub side: Side,
+    /// Limit price, `None` for a market order.
+    pub price: Option<i64>,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimOrder {
+    pub id: u64,
+    pub instrument_id: u32,
+    pub publisher: Publisher,
+    pub side: Side,
+    /// Limit price, `None` for a market order.
+    pub price: Option<i64>,
+    pub size: u32,
+    pub filled: u32,
+    pub ts_submitted: u64,
+    /// Resting size estimated to be ahead of this order in the queue.
+    pub queue_ahead: u32,
+    // Ids of the venue orders that were resting at the level when this order joined it
+    ahead: HashSet<u64>,
+    // Trade volume already taken from `queue_ahead` whose cancels haven't arrived yet
+    unsettled: u32,
+    // Size of each crossing opposite level this order already filled against, so the same
+    // resting liquidity isn't matched twice
+    consumed: HashMap<i64, u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub order_id: u64,
+    pub instrument_id: u32,
+    pub publisher: Publisher,
+    pub side: Side,
+    pub price: i64,
+    pub size: u32,
+    pub ts: u64,
+    /// Whether the fill took liquidity from the book.
+    pub aggressive: bool,
+}
+
+/// Simulated matching layer: fills strategy orders against the reconstructed
+/// book and the estimated queue position, without impacting the book itself.
+#[derive(Debug, Default)]
+pub struct SimExchange {
+    orders: HashMap<u64, SimOrder>,
+    fills: Vec<Fill>,
+    dispatched: usize,
+}
+
+pub struct Backtest<S> {
+    strategy: S,
+    market: Market,
+    // Strategy's delayed copy of `market`, only kept with market-data latency
+    view: Option<Market>,
+    exchange: SimExchange,
+    portfolio: Portfolio,
+    pending: Pending,
+    market_data: LatencyModel,
+    deliveries: VecDeque<(u64, Delivery)>,
+    now: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BacktestConfig {
+    pub latency: LatencyConfig,
+    pub accounting: AccountingConfig,
+}
+
+/// Strategy actions that haven't reached the exchange yet, plus timers.
+#[derive(Debug, Default)]
+struct Pending {
+    next_order_id: u64,
+    order_entry: LatencyModel,
+    actions: VecDeque<(u64, OrderAction)>,
+    timers: BinaryHeap<Reverse<u64>>,
+}
+
+#[derive(Debug)]
+enum OrderAction {
+    Submit(OrderRequest),
+    Cancel(u64),
+}
+
+/// Exchange output on its way to the strategy.
+#[derive(Debug)]
+enum Delivery {
+    Market(MboMsg),
+    Fill(Fill),
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestReport {
+    pub fills: Vec<Fill>,
+    pub accounting: AccountingReport,
+}
+
+impl Context<'_> {
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn market(&self) -> &Market {
+        self.market
+    }
+
+    pub fn submit_limit(
+        &mut self,
+        instrument_id: u32,
+        publisher: Publisher,
+        side: Side,
+        price: i64,
+        size: u32,
+    ) -> u64 {
+        self.submit(instrument_id, publisher, side, Some(price), size)
+    }
+
+    /// Submit a market order; whatever can't be filled against the book is cancelled.
+    pub fn submit_market(
+        &mut self,
+        instrument_id: u32,
+        publisher: Publisher,
+        side: Side,
+        size: u32,
+    ) -> u64 {
+        self.submit(instrument_id, publisher, side, None, size)
+    }
+
+    /// Request cancellation; it has no effect if the order fills before the cancel arrives.
+    pub fn cancel(&mut self, order_id: u64) {
+        let publisher = self
+            .exchange
+            .order(order_id)
+            .map(|order| order.publisher)
+            .or_else(|| self.pending.publisher(order_id));
+        if let Some(publisher) = publisher {
+            self.pending
+                .send(self.now, publisher, OrderAction::Cancel(order_id));
+        }
+    }
+
+    pub fn schedule_timer(&mut self, ts: u64) {
+        self.pending.timers.push(Reverse(ts));
+    }
+
+    pub fn order(&self, order_id: u64) -> Option<&SimOrder> {
+        self.exchange.order(order_id)
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &SimOrder> {
+        self.exchange.open_orders()
+    }
+
+    pub fn position(&self, instrument_id: u32) -> Option<&Position> {
+        self.portfolio.position(instrument_id)
+    }
+
+    pub fn portfolio(&self) -> &Portfolio {
+        self.portfolio
+    }
+
+    fn submit(
+        &mut self,
+        instrument_id: u32,
+        publisher: Publisher,
+        side: Side,
+        price: Option<i64>,
+        size: u32,
+    ) -> u64 {
+        self.pending.next_order_id += 1;
+        let id = self.pending.next_order_id;
+        let request = OrderRequest {
+            id,
+            instrument_id,
+            publisher,
+            side,
+            price,
+            size,
+        };
+        self.pending
+            .send(self.now, publisher, OrderAction::Submit(request));
+        id
+    }
+}
+
+impl Pending {
+    fn send(&mut self, now: u64, publisher: Publisher, action: OrderAction) {
+        // Actions share one session, so they can't overtake each other
+        let last = self.actions.back().map(|(ts, _)| *ts).unwrap_or_default();
+        let arrival = (now + self.order_entry.sample(publisher)).max(last);
+        self.actions.push_back((arrival, action));
+    }
+
+    fn publisher(&self, order_id: u64) -> Option<Publisher> {
+        self.actions.iter().find_map(|(_, action)| match action {
+            OrderAction::Submit(request) if request.id == order_id => Some(request.publisher),
+            _ => None,
+        })
+    }
+}
+
+impl SimOrder {
+    pub fn remaining(&self) -> u32 {
+        self.size - self.filled
+    }
+
+    fn is_marketable(&self, price: i64) -> bool {
+        match (self.side, self.price) {
+            (_, None) => true,
+            (Side::Bid, Some(limit)) => price <= limit,
+            (Side::Ask, Some(limit)) => price >= limit,
+            (Side::None, _) => false,
+        }
+    }
+
+    fn matches(&self, mbo: &MboMsg) -> bool {
+        self.instrument_id == mbo.hd.instrument_id && mbo.hd.publisher_id == self.publisher as u16
+    }
+
+    fn fill(&mut self, price: i64, size: u32, ts: u64, aggressive: bool) -> Fill {
+        self.filled += size;
+        Fill {
+            order_id: self.id,
+            instrument_id: self.instrument_id,
+            publisher: self.publisher,
+            side: self.side,
+            price,
+            size,
+            ts,
+            aggressive,
+        }
+    }
+}
+
+impl SimExchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn order(&self, order_id: u64) -> Option<&SimOrder> {
+        self.orders.get(&order_id)
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &SimOrder> {
+        self.orders.values()
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    /// Match `request` against the book and rest what's left of a limit order. Orders without
+    /// a side are rejected: they're neither filled nor stored.
+    pub fn submit(&mut self, market: &Market, request: OrderRequest, ts: u64) {
+        if request.side == Side::None {
+            return;
+        }
+        let OrderRequest {
+            id,
+            instrument_id,
+            publisher,
+            side,
+            price,
+            size,
+        } = request;
+        let mut order = SimOrder {
+            id,
+            instrument_id,
+            publisher,
+            side,
+            price,
+            size,
+            filled: 0,
+            ts_submitted: ts,
+            queue_ahead: 0,
+            ahead: HashSet::new(),
+            unsettled: 0,
+            consumed: HashMap::new(),
+        };
+        let book = market.book(instrument_id, publisher);
+        if let Some(book) = book {
+            self.take_liquidity(book, &mut order, ts);
+        }
+        // Market orders are IOC: the unfilled part is dropped
+        if let (Some(price), true) = (order.price, order.remaining() > 0) {
+            if let Some(book) = book {
+                for resting in book.level_orders(side, price) {
+                    order.ahead.insert(resting.order_id);
+                    order.queue_ahead += resting.size;
+                }
+            }
+            self.orders.insert(id, order);
+        }
+    }
+
+    pub fn cancel(&mut self, order_id: u64) -> bool {
+        self.orders.remove(&order_id).is_some()
+    }
+
+    /// Update simulated orders for `mbo` before it is applied to `market`.
+    pub fn on_event(&mut self, market: &Market, mbo: &MboMsg) {
+        let Ok(action) = mbo.action() else {
+            return;
+        };
+        match action {
+            Action::Trade => self.on_trade(mbo),
+            Action::Cancel | Action::Modify => self.on_order_change(market, mbo, action),
+            Action::Clear => {
+                for order in self.orders.values_mut().filter(|o| o.matches(mbo)) {
+                    order.ahead.clear();
+                    order.queue_ahead = 0;
+                    order.unsettled = 0;
+                }
+            }
+            Action::Add | Action::Fill | Action::None => {}
+        }
+    }
+
+    /// Fill resting orders against liquidity that newly crossed them after `mbo` was applied.
+    pub fn after_event(&mut self, market: &Market, mbo: &MboMsg) {
+        let Ok(publisher) = mbo.publisher() else {
+            return;
+        };
+        let Some(book) = market.book(mbo.hd.instrument_id, publisher) else {
+            return;
+        };
+        let ts = mbo.ts_recv;
+        let mut fills = Vec::new();
+        for order in self.orders.values_mut().filter(|o| o.matches(mbo)) {
+            let Some(limit) = order.price else {
+                continue;
+            };
+            let crossing: Vec<_> = match order.side {
+                Side::Bid => book
+                    .ask_levels()
+                    .take_while(|level| level.price <= limit)
+                    .collect(),
+                Side::Ask => book
+                    .bid_levels()
+                    .take_while(|level| level.price >= limit)
+                    .collect(),
+                Side::None => Vec::new(),
+            };
+            // Liquidity that left a level since it was matched can't be matched again, while
+            // size added back to it is new
+            let mut consumed = HashMap::new();
+            let mut size = 0;
+            for level in crossing {
+                let used = order
+                    .consumed
+                    .get(&level.price)
+                    .map_or(0, |used| (*used).min(level.size));
+                let new = (level.size - used).min(order.remaining() - size);
+                size += new;
+                consumed.insert(level.price, used + new);
+            }
+            order.consumed = consumed;
+            if size > 0 {
+                fills.push(order.fill(limit, size, ts, false));
+            }
+        }
+        for fill in fills {
+            self.record_fill(fill);
+        }
+    }
+
+    fn take_liquidity(&mut self, book: &Book, order: &mut SimOrder, ts: u64) {
+        let levels: Box<dyn Iterator<Item = _>> = match order.side {
+            Side::Bid => Box::new(book.ask_levels()),
+            Side::Ask => Box::new(book.bid_levels()),
+            Side::None => return,
+        };
+        for level in levels {
+            if order.remaining() == 0 || !order.is_marketable(level.price) {
+                break;
+            }
+            let size = level.size.min(order.remaining());
+            order.consumed.insert(level.price, size);
+            let fill = order.fill(level.price, size, ts, true);
+            self.record_fill(fill);
+        }
+    }
+
+    fn on_trade(&mut self, trade: &MboMsg) {
+        // The trade side is the aggressor, so it can only hit resting orders on the other side
+        let resting_side = match trade.side() {
+            Ok(Side::Ask) => Side::Bid,
+            Ok(Side::Bid) => Side::Ask,
+            _ => return,
+        };
+        let mut fills = Vec::new();
+        for order in self.orders.values_mut() {
+            if !order.matches(trade) || order.side != resting_side {
+                continue;
+            }
+            let Some(limit) = order.price else {
+                continue;
+            };
+            let size = if limit == trade.price {
+                let consumed = trade.size.min(order.queue_ahead);
+                order.queue_ahead -= consumed;
+                order.unsettled += consumed;
+                trade.size - consumed
+            } else if order.is_marketable(trade.price) {
+                // The aggressor traded through our price, so it would have hit us first
+                trade.size
+            } else {
+                0
+            };
+            let size = size.min(order.remaining());
+            if size > 0 {
+                fills.push(order.fill(limit, size, trade.ts_recv, false));
+            }
+        }
+        for fill in fills {
+            self.record_fill(fill);
+        }
+    }
+
+    fn on_order_change(&mut self, market: &Market, mbo: &MboMsg, action: Action) {
+        let Ok(publisher) = mbo.publisher() else {
+            return;
+        };
+        let Some(resting) = market
+            .book(mbo.hd.instrument_id, publisher)
+            .and_then(|book| book.order(mbo.order_id))
+        else {
+            return;
+        };
+        for order in self.orders.values_mut() {
+            if !order.matches(mbo) || !order.ahead.contains(&mbo.order_id) {
+                continue;
+            }
+            let removed = match action {
+                Action::Cancel => mbo.size.min(resting.size),
+                // Moving or growing an order sends it to the back of the queue
+                _ if mbo.price != resting.price || mbo.size > resting.size => resting.size,
+                _ => resting.size - mbo.size,
+            };
+            // Cancels following a trade at our level settle volume already taken off the queue
+            let settled = if action == Action::Cancel {
+                removed.min(order.unsettled)
+            } else {
+                0
+            };
+            order.unsettled -= settled;
+            order.queue_ahead = order.queue_ahead.saturating_sub(removed - settled);
+            let leaves_queue = match action {
+                Action::Cancel => removed == resting.size,
+                _ => mbo.price != resting.price || mbo.size > resting.size || mbo.size == 0,
+            };
+            if leaves_queue {
+                order.ahead.remove(&mbo.order_id);
+            }
+        }
+    }
+
+    fn record_fill(&mut self, fill: Fill) {
+        if let Some(order) = self.orders.get(&fill.order_id) {
+            if order.remaining() == 0 {
+                self.orders.remove(&fill.order_id);
+            }
+        }
+        self.fills.push(fill);
+    }
+
+    fn take_new_fills(&mut self) -> Vec<Fill> {
+        let fills = self.fills[self.dispatched..].to_vec();
+        self.dispatched = self.fills.len();
+        fills
+    }
+}
+
+impl<S: Strategy> Backtest<S> {
+    pub fn new(strategy: S) -> Self {
+        Self::with_config(strategy, BacktestConfig::default())
+    }
+
+    pub fn with_config(strategy: S, config: BacktestConfig) -> Self {
+        let BacktestConfig {
+            latency,
+            accounting,
+        } = config;
+        let view = (!latency.market_data.is_zero()).then(Market::new);
+        Self {
+            strategy,
+            market: Market::new(),
+            view,
+            exchange: SimExchange::new(),
+            portfolio: Portfolio::new(accounting),
+            pending: Pending {
+                order_entry: latency.order_entry,
+                ..Default::default()
+            },
+            market_data: latency.market_data,
+            deliveries: VecDeque::new(),
+            now: 0,
+        }
+    }
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    /// The book as seen by the simulated exchange.
+    pub fn market(&self) -> &Market {
+        &self.market
+    }
+
+    pub fn exchange(&self) -> &SimExchange {
+        &self.exchange
+    }
+
+    pub fn portfolio(&self) -> &Portfolio {
+        &self.portfolio
+    }
+
+    pub fn portfolio_mut(&mut self) -> &mut Portfolio {
+        &mut self.portfolio
+    }
+
+    /// Replay every MBO record of a DBN file through the backtest.
+    pub async fn run_file(&mut self, path: impl AsRef<Path>) -> databento::dbn::Result<()> {
+        let mut decoder = file_decoder(path).await?;
+        self.portfolio.load_symbology(decoder.metadata());
+        while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
+            self.process(mbo);
+        }
+        Ok(())
+    }
+
+    pub fn process(&mut self, mbo: &MboMsg) {
+        let ts = mbo.ts_recv;
+        self.run_until(ts);
+        self.now = ts;
+
+        self.exchange.on_event(&self.market, mbo);
+        self.market.apply(mbo.clone());
+        self.exchange.after_event(&self.market, mbo);
+        if matches!(mbo.action(), Ok(Action::Trade)) {
+            self.portfolio.on_trade(mbo.hd.instrument_id, mbo.price);
+        }
+        self.deliver_fills();
+        self.deliver(Delivery::Market(mbo.clone()));
+
+        // Zero-latency deliveries and actions happen at this same timestamp
+        self.run_until(ts);
+    }
+
+    pub fn report(&self) -> BacktestReport {
+        BacktestReport {
+            fills: self.exchange.fills().to_vec(),
+            accounting: self.portfolio.report(&self.market),
+        }
+    }
+
+    pub fn into_strategy(self) -> S {
+        self.strategy
+    }
+
+    /// Handle deliveries, timers and order actions due at or before `ts` in time order.
+    fn run_until(&mut self, ts: u64) {
+        loop {
+            let delivery_ts = self.deliveries.front().map(|(ts, _)| *ts);
+            let timer_ts = self.pending.timers.peek().map(|Reverse(ts)| *ts);
+            let action_ts = self.pending.actions.front().map(|(ts, _)| *ts);
+            let Some(next_ts) = [delivery_ts, timer_ts, action_ts]
+                .into_iter()
+                .flatten()
+                .min()
+                .filter(|next_ts| *next_ts <= ts)
+            else {
+                break;
+            };
+            self.now = self.now.max(next_ts);
+            if delivery_ts == Some(next_ts) {
+                let (_, delivery) = self.deliveries.pop_front().unwrap();
+                self.on_delivery(delivery);
+            } else if timer_ts == Some(next_ts) {
+                self.pending.timers.pop();
+                self.with_context(|strategy, ctx| strategy.on_timer(ctx, next_ts));
+            } else {
+                let (_, action) = self.pending.actions.pop_front().unwrap();
+                match action {
+                    OrderAction::Submit(request) => {
+                        self.exchange.submit(&self.market, request, next_ts)
+                    }
+                    OrderAction::Cancel(order_id) => {
+                        self.exchange.cancel(order_id);
+                    }
+                }
+                self.deliver_fills();
+            }
+        }
+    }
+
+    fn on_delivery(&mut self, delivery: Delivery) {
+        match delivery {
+            Delivery::Fill(fill) => {
+                self.with_context(|strategy, ctx| strategy.on_fill(ctx, &fill));
+            }
+            Delivery::Market(mbo) => {
+                if let Some(view) = self.view.as_mut() {
+                    view.apply(mbo.clone());
+                }
+                let action = mbo.action().unwrap_or(Action::None);
+                if action == Action::Trade {
+                    self.with_context(|strategy, ctx| strategy.on_trade(ctx, &mbo));
+                } else if mbo.flags.is_last() && !matches!(action, Action::Fill | Action::None) {
+                    self.with_context(|strategy, ctx| strategy.on_book_update(ctx, &mbo));
+                }
+            }
+        }
+    }
+
+    fn deliver_fills(&mut self) {
+        for fill in self.exchange.take_new_fills() {
+            self.portfolio.apply_fill(&fill);
+            self.deliver(Delivery::Fill(fill));
+        }
+    }
+
+    fn deliver(&mut self, delivery: Delivery) {
+        let publisher = match &delivery {
+            Delivery::Fill(fill) => fill.publisher,
+            Delivery::Market(mbo) => match mbo.publisher() {
+                Ok(publisher) => publisher,
+                Err(_) => return,
+            },
+        };
+        // Keep the feed in order even when sampled latencies would reorder it
+        let last = self
+            .deliveries
+            .back()
+            .map(|(ts, _)| *ts)
+            .unwrap_or_default();
+        let ts = (self.now + self.market_data.sample(publisher)).max(last);
+        self.deliveries.push_back((ts, delivery));
+    }
+
+    fn with_context(&mut self, f: impl FnOnce(&mut S, &mut Context<'_>)) {
+        let mut ctx = Context {
+            now: self.now,
+            market: self.view.as_ref().unwrap_or(&self.market),
+            exchange: &self.exchange,
+            portfolio: &self.portfolio,
+            pending: &mut self.pending,
+        };
+        f(&mut self.strategy, &mut ctx);
+    }
+}
@@ -1,13 +1,18 @@
 use std::net::SocketAddr;
 
-use ::mbo_orderbook::common::print_pretty;
+use ::mbo_orderbook::{
+    common::print_pretty,
+    compression::decompressed,
+    pacing::Speed,
+    subscription::{subscribe, Subscription},
+};
 use anyhow::Result;
 use clap::Parser;
 use databento::dbn::{
     decode::{AsyncDbnDecoder, DbnMetadata},
-    MboMsg,
+    MboMsg, Schema,
 };
-use mbo_orderbook::orderbook::Market;
+use mbo_orderbook::{monitor::BookMonitor, orderbook::Market};
 use tokio::net::TcpStream; // crate name = package name from Cargo.toml
 
 /// Connect to mbo-streammer, read DBN MBO data, decode, and print records.
@@ -35,6 +40,27 @@ struct Args {
     /// Pretty-print records instead of raw Debug
     #[arg(long)]
     pretty: bool,
+
+    /// Validate books after every event, a full pass over each book; crossed/locked books are
+    /// reported in debug builds even without it
+    #[arg(long)]
+    check: bool,
+
+    /// Subscribe to these raw symbols only
+    #[arg(long = "symbol", value_name = "SYMBOL", value_delimiter = ',')]
+    symbols: Vec<String>,
+
+    /// Subscribe from this UNIX time in nanoseconds
+    #[arg(long, value_name = "NS")]
+    start: Option<u64>,
+
+    /// Replay speed to ask for, e.g. 2x or max
+    #[arg(long)]
+    speed: Option<Speed>,
+
+    /// Skip the subscription handshake (for mbo-streamer-raw and older servers)
+    #[arg(long, conflicts_with_all = ["symbols", "start", "speed"])]
+    legacy: bool,
 }
 
 #[tokio::main]
@@ -44,11 +70,22 @@ async fn main() -> Result<()> {
     let addr: SocketAddr = args.addr.parse()?;
     println!("Connecting to mbo-streamer at {}", addr);
 
-    let stream = TcpStream::connect(addr).await?;
+    let mut stream = TcpStream::connect(addr).await?;
+    if !args.legacy {
+        let subscription = Subscription {
+            symbols: args.symbols.clone(),
+            schema: Some(Schema::Mbo),
+            start: args.start,
+            speed: args.speed,
+        };
+        subscribe(&mut stream, &subscription).await?;
+        println!("Subscribed: {subscription}");
+    }
     println!("Connected, starting to read DBN stream…");
 
-    // AsyncDbnDecoder can take any AsyncRead (like TcpStream)
-    let mut decoder = AsyncDbnDecoder::new(stream).await?;
+    // AsyncDbnDecoder can take any AsyncRead (like TcpStream), here decompressed if the
+    // streamer sends zstd
+    let mut decoder = AsyncDbnDecoder::new(decompressed(stream).await?).await?;
 
     // We can inspect metadata if desired
     let metadata = decoder.metadata().clone();
@@ -58,6 +95,11 @@ async fn main() -> Result<()> {
     );
 
     let mut market = Market::new();
+    if args.check {
+        // So `Book::validate` can check the incremental hash against a full one
+        market = market.with_state_hash();
+    }
+    let mut monitor = (args.check || cfg!(debug_assertions)).then(|| BookMonitor::new(args.check));
 
     let mut rec_idx: usize = 0;
 
@@ -73,6 +115,12 @@ async fn main() -> Result<()> {
 
         market.apply(mbo.clone());
 
+        if let Some(monitor) = monitor.as_mut() {
+            for event in monitor.check(&market, mbo) {
+                eprintln!("{event}");
+            }
+        }
+
         if args.pretty {
             // e.g. get BBO for a specific instrument / publisher
             let (bid, ask) = market.aggregated_bbo(mbo.hd.instrument_id);
@@ -0,0 +1,91 @@
+use ::mbo_orderbook::{compression::file_decoder, dump::MarketDump, orderbook::Market};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Parser;
+
+use databento::dbn::MboMsg;
+use serde::Serialize;
+
+/// Replay an MBO DBN file and dump the books as JSON at checkpoints.
+#[derive(Parser, Debug)]
+#[command(
+    name = "mbo-dump",
+    version,
+    about = "Dump reconstructed MBO order books (DBN) as JSON lines",
+    long_about = None
+)]
+struct Args {
+    /// Path to the input DBN file
+    #[arg(value_name = "DBN_FILE")]
+    input: PathBuf,
+
+    /// Dump after every N records (0 = only at the end)
+    #[arg(long, default_value_t = 0)]
+    every: usize,
+
+    /// Also dump after these record numbers (1-based), e.g. `--at 10,250`
+    #[arg(long, value_delimiter = ',')]
+    at: Vec<usize>,
+
+    /// Leave out the orders of each level
+    #[arg(long)]
+    no_orders: bool,
+
+    /// Write to this path instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+/// One line of output: the market after `record` records.
+#[derive(Serialize)]
+struct Checkpoint {
+    record: usize,
+    market: MarketDump,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let mut decoder = file_decoder(&args.input).await?;
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut market = Market::new();
+
+    let mut rec_idx = 0;
+    let mut last_dump = 0;
+    while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
+        rec_idx += 1;
+        market.apply(mbo.clone());
+        if (args.every > 0 && rec_idx % args.every == 0) || args.at.contains(&rec_idx) {
+            write_checkpoint(&mut out, &market, rec_idx, !args.no_orders)?;
+            last_dump = rec_idx;
+        }
+    }
+    if last_dump != rec_idx {
+        write_checkpoint(&mut out, &market, rec_idx, !args.no_orders)?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn write_checkpoint(
+    out: &mut impl Write,
+    market: &Market,
+    record: usize,
+    include_orders: bool,
+) -> anyhow::Result<()> {
+    let checkpoint = Checkpoint {
+        record,
+        market: MarketDump::new(market, include_orders),
+    };
+    serde_json::to_writer(&mut *out, &checkpoint)?;
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use ::mbo_orderbook::{common::print_pretty, compression::file_decoder, lint::Linter};
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use clap::Parser;
+
+use databento::dbn::MboMsg;
+
+/// Replay an MBO DBN file and report anomalies in the data.
+#[derive(Parser, Debug)]
+#[command(
+    name = "mbo-lint",
+    version,
+    about = "Report anomalies in MBO market data (DBN)",
+    long_about = None
+)]
+struct Args {
+    /// Path to the input DBN file
+    #[arg(value_name = "DBN_FILE")]
+    input: PathBuf,
+
+    /// Number of offending records to print (0 = none)
+    #[arg(long, short = 'n', default_value_t = 20)]
+    examples: usize,
+
+    /// Write the report as JSON to this path
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Report sequence gaps; only meaningful for data with every instrument of the channel
+    #[arg(long)]
+    sequence_gaps: bool,
+
+    /// Exit with an error if any anomaly is found
+    #[arg(long)]
+    deny: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let mut decoder = file_decoder(&args.input).await?;
+    let mut linter = if args.sequence_gaps {
+        Linter::with_sequence_gaps()
+    } else {
+        Linter::new()
+    };
+
+    let mut rec_idx = 0;
+    let mut printed = 0;
+    while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
+        rec_idx += 1;
+        let anomalies = linter.lint(mbo);
+        if !anomalies.is_empty() && printed < args.examples {
+            printed += 1;
+            println!("{anomalies:?}");
+            print_pretty(rec_idx, mbo);
+        }
+    }
+
+    let report = linter.report();
+    println!("\nLinted {} records", report.records);
+    if report.anomalies.is_empty() {
+        println!("No anomalies found");
+    }
+    for book in &report.books {
+        let publisher = book
+            .publisher
+            .map_or_else(|| book.publisher_id.to_string(), |p| p.to_string());
+        println!("instr_id={} publisher={publisher}", book.instrument_id);
+        for (anomaly, count) in &book.anomalies {
+            println!("  {anomaly:?}: {count}");
+        }
+    }
+    if let Some(path) = &args.output {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &report)?;
+        println!("Wrote report to {:?}", path);
+    }
+    if args.deny && !report.anomalies.is_empty() {
+        anyhow::bail!("{} anomaly type(s) found", report.anomalies.len());
+    }
+
+    Ok(())
+}
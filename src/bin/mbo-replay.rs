@@ -1,9 +1,14 @@
-use ::mbo_orderbook::common::print_pretty;
+use ::mbo_orderbook::{
+    common::print_pretty,
+    merge::{expand_paths, MergedDecoder},
+    order_stats::OrderStats,
+    orderbook::Market,
+};
 use std::path::PathBuf;
 
 use clap::Parser;
 
-use databento::dbn::{decode::AsyncDbnDecoder, MboMsg};
+use databento::dbn::{pretty, MboMsg, Record};
 
 /// Replay MBO records from a DBN file.
 #[derive(Parser, Debug)]
@@ -14,9 +19,9 @@ use databento::dbn::{decode::AsyncDbnDecoder, MboMsg};
     long_about = None
 )]
 struct Args {
-    /// Path to the input DBN file
-    #[arg(value_name = "DBN_FILE")]
-    input: PathBuf,
+    /// Paths or glob patterns of the input DBN files; several files are merged by `ts_recv`
+    #[arg(value_name = "DBN_FILE", required = true)]
+    inputs: Vec<PathBuf>,
 
     /// Maximum number of records to print (0 = no limit)
     #[arg(long, short, default_value_t = 0)]
@@ -25,23 +30,62 @@ struct Args {
     /// Pretty-print records instead of raw Debug
     #[arg(long)]
     pretty: bool,
+
+    /// Don't print records (useful with report options)
+    #[arg(long, short)]
+    quiet: bool,
+
+    /// Write order lifetime and cancellation statistics (JSON) to this path
+    #[arg(long, value_name = "PATH")]
+    order_stats: Option<PathBuf>,
+
+    /// Dump the event journal of this order ID after the replay
+    #[arg(long, value_name = "ORDER_ID")]
+    order: Option<u64>,
+
+    /// Print the state hash of the updated book after every N records
+    /// (`<record> <ts_recv> <instrument_id> <publisher_id> <hash>`)
+    #[arg(long, value_name = "N")]
+    hash_every: Option<usize>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let path = args.input;
-    println!("Reading DBN file: {:?}", path);
+    let paths = expand_paths(&args.inputs)?;
+    eprintln!("Reading DBN files: {:?}", paths);
 
-    let mut decoder = AsyncDbnDecoder::from_file(path).await?;
+    let mut decoder = MergedDecoder::open(&paths).await?;
+
+    let mut market = Market::new();
+    if args.order.is_some() {
+        market = market.with_journal();
+    }
+    if args.hash_every.is_some() {
+        market = market.with_state_hash();
+    }
+    let mut order_stats = args.order_stats.as_ref().map(|_| OrderStats::new());
 
     let mut rec_idx = 0;
-    while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
+    while let Some(mbo) = decoder.decode_record().await? {
         rec_idx += 1;
-        if args.pretty {
-            print_pretty(rec_idx, mbo);
-        } else {
-            println!("{rec_idx}: {:?}", mbo);
+        if let Some(order_stats) = order_stats.as_mut() {
+            order_stats.apply(&mut market, mbo);
+        } else if args.order.is_some() || args.hash_every.is_some() {
+            market.apply(mbo.clone());
+        }
+        if args
+            .hash_every
+            .is_some_and(|every| rec_idx % every.max(1) == 0)
+        {
+            print_hash(&market, rec_idx, mbo);
+        }
+        if !args.quiet {
+            if args.pretty {
+                print_pretty(rec_idx, mbo);
+            } else {
+                println!("{rec_idx}: {:?}", mbo);
+            }
         }
 
         if args.limit > 0 && rec_idx >= args.limit {
@@ -49,5 +93,56 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    if let (Some(path), Some(order_stats)) = (&args.order_stats, &order_stats) {
+        order_stats.report().write_to_file(path)?;
+        println!("Wrote order statistics to {:?}", path);
+    }
+
+    if let Some(order_id) = args.order {
+        print_journal(&market, order_id);
+    }
+
     Ok(())
 }
+
+fn print_journal(market: &Market, order_id: u64) {
+    let journals = market.order_journal(order_id);
+    if journals.is_empty() {
+        println!("Order {order_id} not found");
+    }
+    for (instrument_id, publisher, entries) in journals {
+        println!("Order {order_id} instr_id={instrument_id} publisher={publisher}");
+        for entry in entries {
+            let queue_pos = entry
+                .queue_pos
+                .map_or_else(|| "-".to_owned(), |pos| pos.to_string());
+            println!(
+                "  ts_event={} ts_recv={} seq={} action={:?} side={:?} px={:.2} qty={:<4} remaining={:<4} queue_pos={}",
+                entry.ts_event,
+                entry.ts_recv,
+                entry.sequence,
+                entry.action,
+                entry.side,
+                pretty::Px(entry.price),
+                entry.size,
+                entry.remaining,
+                queue_pos,
+            );
+        }
+    }
+}
+
+fn print_hash(market: &Market, rec_idx: usize, mbo: &MboMsg) {
+    let Ok(publisher) = mbo.publisher() else {
+        return;
+    };
+    if let Some(book) = market.book(mbo.hd.instrument_id, publisher) {
+        println!(
+            "{rec_idx} {} {} {} {:016x}",
+            mbo.ts_recv,
+            mbo.hd.instrument_id,
+            mbo.hd.publisher_id,
+            book.state_hash()
+        );
+    }
+}
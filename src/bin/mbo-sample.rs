@@ -0,0 +1,107 @@
+use ::mbo_orderbook::{
+    compression::file_decoder,
+    orderbook::Market,
+    sampler::{SampleFormat, SampleWriter, Sampler, Trigger},
+};
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use databento::dbn::{decode::DbnMetadata, MboMsg};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum Mode {
+    /// Sample every book at fixed `ts_event` intervals
+    Interval,
+    /// Sample a book after every event (F_LAST) that touches it
+    Event,
+    /// Sample a book after every N trades
+    Trades,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum Format {
+    Csv,
+    Jsonl,
+    /// DBN MBP-10 (at most 10 levels)
+    Mbp10,
+}
+
+/// Replay an MBO DBN file through the order book and write sampled top-N depth.
+#[derive(Parser, Debug)]
+#[command(
+    name = "mbo-sample",
+    version,
+    about = "Sample top-N order book depth from MBO market data (DBN)",
+    long_about = None
+)]
+struct Args {
+    /// Path to the input DBN file
+    #[arg(value_name = "DBN_FILE")]
+    input: PathBuf,
+
+    /// Path to the output file
+    #[arg(long, short)]
+    output: PathBuf,
+
+    /// Number of levels per side
+    #[arg(long, default_value_t = 10)]
+    levels: usize,
+
+    /// When to sample
+    #[arg(long, value_enum, default_value_t = Mode::Interval)]
+    mode: Mode,
+
+    /// Sampling interval in milliseconds (interval mode)
+    #[arg(long, default_value_t = 100)]
+    interval_ms: u64,
+
+    /// Number of trades between samples (trades mode)
+    #[arg(long, default_value_t = 1)]
+    trades: u64,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let trigger = match args.mode {
+        Mode::Interval => Trigger::Interval {
+            interval_ns: args.interval_ms.max(1) * 1_000_000,
+        },
+        Mode::Event => Trigger::Event,
+        Mode::Trades => Trigger::Trades {
+            count: args.trades.max(1),
+        },
+    };
+    let format = match args.format {
+        Format::Csv => SampleFormat::Csv,
+        Format::Jsonl => SampleFormat::JsonLines,
+        Format::Mbp10 => SampleFormat::Mbp10,
+    };
+    let levels = if format == SampleFormat::Mbp10 {
+        args.levels.min(10)
+    } else {
+        args.levels
+    };
+
+    let mut decoder = file_decoder(&args.input).await?;
+    let mut writer = SampleWriter::create(&args.output, format, levels, decoder.metadata())?;
+    let mut sampler = Sampler::new(trigger, levels);
+    let mut market = Market::new();
+
+    let mut sample_count = 0;
+    while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
+        for sample in sampler.apply(&mut market, mbo) {
+            writer.write(&sample)?;
+            sample_count += 1;
+        }
+    }
+    writer.finish()?;
+    eprintln!("Wrote {sample_count} samples to {:?}", args.output);
+
+    Ok(())
+}
@@ -1,13 +1,21 @@
+use ::mbo_orderbook::{
+    compression::{compressed, decompressed, DbnWriter},
+    outbox::{Outbox, OutboxConfig, OutboxStats, SlowConsumer},
+};
 use anyhow::Result;
 use clap::Parser;
 use clap::ValueEnum;
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use databento::dbn::decode::DbnMetadataDecoder;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use tokio::{
     fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt},
     net::TcpListener,
 };
 
+/// Bytes of records queued per push; drop-oldest drops whole chunks.
+const CHUNK_LEN: usize = 64 * 1024;
+
 /// Replay MBO records from a DBN file.
 #[derive(Parser, Debug)]
 #[command(
@@ -28,6 +36,22 @@ struct Args {
     /// Mode: buffered (load into memory once) or streaming (re-read file per client)
     #[arg(long, short, value_enum, default_value_t = Mode::Buffered)]
     mode: Mode,
+
+    /// What to do with a client whose queue is full: block, drop-oldest or disconnect
+    #[arg(long, value_name = "POLICY", default_value_t = SlowConsumer::Block)]
+    slow_consumer: SlowConsumer,
+
+    /// Most bytes queued per client
+    #[arg(long, value_name = "BYTES", default_value_t = 4 << 20)]
+    queue_bytes: usize,
+
+    /// With `--slow-consumer disconnect`, how far behind in milliseconds a client may fall
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    max_lag_ms: u64,
+
+    /// Compress the stream sent to clients with zstd
+    #[arg(long)]
+    zstd: bool,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -40,10 +64,16 @@ enum Mode {
 async fn main() -> Result<()> {
     let args = Args::parse();
     let path = args.input;
+    let config = OutboxConfig {
+        capacity: args.queue_bytes,
+        policy: args.slow_consumer,
+        max_lag: Duration::from_millis(args.max_lag_ms),
+    };
 
     let addr: SocketAddr = args.bind.parse()?;
     let listener = TcpListener::bind(addr).await?;
     let filepath = Arc::new(path);
+    let zstd = args.zstd;
 
     println!("Listening on {}", addr);
 
@@ -51,68 +81,115 @@ async fn main() -> Result<()> {
         Mode::Buffered => {
             println!("Loading DBN file into memory: {:?}", filepath);
 
-            let mut file = File::open(&*filepath).await?;
+            // Decompressed up front, so the records can be queued whole
+            let file = File::open(&*filepath).await?;
             let mut buf = Vec::new();
-            file.read_to_end(&mut buf).await?;
+            decompressed(file).await?.read_to_end(&mut buf).await?;
             let data = Arc::new(buf);
 
             println!("File loaded ({} bytes). Listening on {}", data.len(), addr);
 
             loop {
-                let (mut socket, peer) = listener.accept().await?;
+                let (socket, peer) = listener.accept().await?;
                 let data = Arc::clone(&data);
 
                 tokio::spawn(async move {
                     println!("New client: {}", peer);
 
-                    if let Err(e) = socket.write_all(&data).await {
-                        eprintln!("Error sending to client: {e}");
-                        return;
+                    match stream_dbn(compressed(socket, zstd), &data[..], config).await {
+                        Ok(stats) => println!("Finished streaming to {}: {stats}", peer),
+                        Err(e) => eprintln!("Error sending to client: {e}"),
                     }
-
-                    let _ = socket.shutdown().await;
-                    println!("Finished streaming to {}", peer);
                 });
             }
         }
-        Mode::Streaming => {
-            loop {
-                let (mut socket, peer) = listener.accept().await?;
-                let filepath = filepath.clone();
+        Mode::Streaming => loop {
+            let (socket, peer) = listener.accept().await?;
+            let filepath = filepath.clone();
+
+            tokio::spawn(async move {
+                println!("New client: {}", peer);
+                println!("Streaming DBN file: {:?}", filepath);
+
+                let file = match File::open(&*filepath).await {
+                    Ok(f) => f,
+                    Err(e) => {
+                        eprintln!("Error opening file: {e}");
+                        return;
+                    }
+                };
 
-                tokio::spawn(async move {
-                    println!("New client: {}", peer);
-                    println!("Streaming DBN file: {:?}", filepath);
-
-                    let mut file = match File::open(&*filepath).await {
-                        Ok(f) => f,
-                        Err(e) => {
-                            eprintln!("Error opening file: {e}");
-                            return;
-                        }
-                    };
-
-                    let mut buf = [0u8; 64 * 1024];
-                    loop {
-                        match file.read(&mut buf).await {
-                            Ok(0) => break, // EOF
-                            Ok(n) => {
-                                if let Err(e) = socket.write_all(&buf[..n]).await {
-                                    eprintln!("Error sending to client: {e}");
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("Error reading file: {e}");
-                                return;
-                            }
-                        }
+                let file = match decompressed(file).await {
+                    Ok(file) => file,
+                    Err(e) => {
+                        eprintln!("Error reading file: {e}");
+                        return;
                     }
+                };
+
+                match stream_dbn(compressed(socket, zstd), file, config).await {
+                    Ok(stats) => println!("Finished streaming to {}: {stats}", peer),
+                    Err(e) => eprintln!("Error sending to client: {e}"),
+                }
+            });
+        },
+    }
+}
 
-                    let _ = socket.shutdown().await;
-                    println!("Finished streaming to {}", peer);
-                });
+/// Send the raw DBN bytes from `reader` through an outbox, queuing whole records so the
+/// slow consumer policy can drop them without breaking the stream.
+async fn stream_dbn<R: AsyncRead + Unpin>(
+    socket: DbnWriter,
+    mut reader: R,
+    config: OutboxConfig,
+) -> Result<OutboxStats> {
+    // Prelude: `DBN`, version and metadata length
+    let mut header = vec![0u8; 8];
+    reader.read_exact(&mut header).await?;
+    if &header[..3] != b"DBN" {
+        anyhow::bail!("not a DBN stream");
+    }
+    let version = header[3];
+    let metadata_len = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+    header.resize(8 + metadata_len, 0);
+    reader.read_exact(&mut header[8..]).await?;
+    let ts_out = DbnMetadataDecoder::new(&header[..]).decode()?.ts_out;
+
+    let mut outbox = Outbox::spawn(socket, config, version, ts_out);
+    outbox.push(header, 0).await?;
+
+    let mut buf = Vec::with_capacity(CHUNK_LEN);
+    loop {
+        let start = buf.len();
+        buf.resize(CHUNK_LEN.max(start + 1), 0);
+        let n = reader.read(&mut buf[start..]).await?;
+        buf.truncate(start + n);
+        if n == 0 {
+            break; // EOF
+        }
+        // Queue the whole records and keep the partial one for the next read
+        let (mut end, mut records) = (0, 0);
+        while let Some(&len) = buf.get(end) {
+            let len = len as usize * 4;
+            if len == 0 {
+                anyhow::bail!("invalid record length at byte {end} of a chunk");
+            }
+            if end + len > buf.len() {
+                break;
             }
+            end += len;
+            records += 1;
+        }
+        if records > 0 {
+            let rest = buf.split_off(end);
+            outbox
+                .push(std::mem::replace(&mut buf, rest), records)
+                .await?;
         }
     }
+    if !buf.is_empty() {
+        anyhow::bail!("DBN stream ends with a partial record");
+    }
+
+    outbox.finish().await
 }
@@ -1,19 +1,33 @@
+use ::mbo_orderbook::{
+    common::now_ns,
+    compression::compressed,
+    filter::{parse_action, RecordFilter},
+    gateway,
+    merge::{expand_paths, MergedDecoder},
+    orderbook::Market,
+    outbox::{Outbox, OutboxConfig, OutboxStats, SlowConsumer},
+    pacing::{Pacer, Speed},
+    rebase::Rebaser,
+    subscription::{read_line, write_line, Reply, Subscription},
+};
 use std::{
+    borrow::Cow,
     net::SocketAddr,
-    path::{Path, PathBuf},
-    sync::Arc,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
 };
 
 use anyhow::Result;
 use clap::Parser;
 use clap::ValueEnum;
-use databento::dbn::encode::AsyncEncodeRecord;
 use databento::dbn::{
-    decode::{AsyncDbnDecoder, DbnMetadata},
-    encode::AsyncDbnEncoder,
-    MboMsg, Metadata,
+    decode::DbnMetadata, encode::DbnMetadataEncoder, Action, ErrorMsg, MboMsg, Metadata, WithTsOut,
 };
-use tokio::{io::BufWriter, net::TcpListener, net::TcpStream};
+use tokio::{net::TcpListener, net::TcpStream, sync::mpsc, task::JoinSet, time};
 
 /// Stream DBN MBO records to any TCP client that connects.
 #[derive(Parser, Debug)]
@@ -24,39 +38,230 @@ use tokio::{io::BufWriter, net::TcpListener, net::TcpStream};
     long_about = None
 )]
 struct Args {
-    /// Path to the input DBN file with MBO records
-    #[arg(value_name = "DBN_FILE")]
-    input: PathBuf,
+    /// Paths or glob patterns of the input DBN files with MBO records; several files are
+    /// merged into one stream by `ts_recv`
+    #[arg(value_name = "DBN_FILE", required = true)]
+    inputs: Vec<PathBuf>,
 
     /// Address to bind the TCP server to, e.g. 0.0.0.0:5000
     #[arg(long, short, default_value = "0.0.0.0:5000")]
     bind: String,
 
-    /// Mode: buffered (load into memory once) or streaming (re-read file per client)
+    /// Mode: buffered (load into memory once), streaming (re-read file per client) or broadcast
+    /// (one shared timeline; clients join at the current position)
     #[arg(long, short, value_enum, default_value_t = Mode::Buffered)]
     mode: Mode,
+
+    /// Release records at the pace of their recorded timestamps instead of as fast as possible
+    #[arg(long)]
+    pace: bool,
+
+    /// Timestamp to pace by
+    #[arg(long, value_enum, default_value_t = PaceClock::TsEvent, requires = "pace")]
+    clock: PaceClock,
+
+    /// Replay speed: a multiplier like 0.5x or 10x, or max
+    #[arg(long, default_value = "1x", requires = "pace")]
+    speed: Speed,
+
+    /// Longest pause between two records in milliseconds; quiet periods are cut short
+    #[arg(long, value_name = "MS", requires = "pace")]
+    max_sleep_ms: Option<u64>,
+
+    /// Replay the file in an endless loop, moving timestamps and sequence numbers forward each
+    /// time and clearing every book in between
+    #[arg(long = "loop")]
+    looping: bool,
+
+    /// Shift timestamps so the replay starts at the current time
+    #[arg(long)]
+    wall_clock: bool,
+
+    /// Only send these instrument IDs
+    #[arg(long = "instrument-id", value_name = "ID", value_delimiter = ',')]
+    instrument_ids: Vec<u32>,
+
+    /// Only send these raw symbols, resolved through the file's symbology
+    #[arg(long = "symbol", value_name = "SYMBOL", value_delimiter = ',')]
+    symbols: Vec<String>,
+
+    /// Only send these publisher IDs
+    #[arg(long = "publisher-id", value_name = "ID", value_delimiter = ',')]
+    publisher_ids: Vec<u16>,
+
+    /// Only send these actions, e.g. `A,C,M` or `trade`
+    #[arg(long = "action", value_name = "ACTION", value_delimiter = ',', value_parser = parse_action)]
+    actions: Vec<Action>,
+
+    /// Only send records with a `ts_event` at or after this UNIX time in nanoseconds
+    #[arg(long, value_name = "NS")]
+    start: Option<u64>,
+
+    /// Only send records with a `ts_event` before this UNIX time in nanoseconds
+    #[arg(long, value_name = "NS")]
+    end: Option<u64>,
+
+    /// How long to wait for a client's subscription before serving it as a legacy client
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    handshake_timeout_ms: u64,
+
+    /// Speak the Databento Live gateway protocol instead, authenticating clients with this 32-character API key
+    #[arg(long, value_name = "KEY", value_parser = parse_live_key)]
+    live_key: Option<String>,
+
+    /// In broadcast mode, wait for this many clients before starting the timeline
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    min_clients: usize,
+
+    /// In broadcast mode, send clients joining mid-stream a snapshot of every book first
+    #[arg(long)]
+    snapshot: bool,
+
+    /// What to do with a client whose queue is full: block, drop-oldest or disconnect
+    #[arg(long, value_name = "POLICY", default_value_t = SlowConsumer::Block)]
+    slow_consumer: SlowConsumer,
+
+    /// Most bytes queued per client
+    #[arg(long, value_name = "BYTES", default_value_t = 4 << 20)]
+    queue_bytes: usize,
+
+    /// With `--slow-consumer disconnect`, how far behind in milliseconds a client may fall
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    max_lag_ms: u64,
+
+    /// Compress the stream sent to clients with zstd; not understood by Live clients
+    #[arg(long, conflicts_with = "live_key")]
+    zstd: bool,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
 enum Mode {
     Buffered,
     Streaming,
+    Broadcast,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum PaceClock {
+    TsEvent,
+    TsRecv,
+}
+
+impl PaceClock {
+    fn ts(self, mbo: &MboMsg) -> u64 {
+        match self {
+            Self::TsEvent => mbo.hd.ts_event,
+            Self::TsRecv => mbo.ts_recv,
+        }
+    }
+}
+
+/// Pacing settings; each client gets its own [`Pacer`].
+#[derive(Copy, Clone, Debug)]
+struct Pace {
+    clock: PaceClock,
+    speed: Speed,
+    max_sleep: Option<Duration>,
+}
+
+/// Server settings shared by every client.
+#[derive(Clone, Debug)]
+struct Config {
+    /// Pacing of clients that don't ask for a speed.
+    pace: Option<Pace>,
+    /// Clock and quiet-period cap for clients that ask for a speed.
+    pace_template: Pace,
+    handshake_timeout: Duration,
+    /// Serve every client as a Databento Live gateway with this API key.
+    live_key: Option<String>,
+    /// Every client shares one timeline, so they can't pick their own speed.
+    broadcast: bool,
+    outbox: OutboxConfig,
+    /// Replay the file forever.
+    looping: bool,
+    /// Shift timestamps to start at the current time.
+    wall_clock: bool,
+    /// `ts_recv` of the file's first record, which the wall clock shifts to the current time.
+    first_ts_recv: u64,
+    /// Timestamp offset of the broadcast timeline, fixed once the first client joins.
+    timeline_offset: OnceLock<u64>,
+    /// Compress what's sent to clients.
+    zstd: bool,
+}
+
+/// What one client is sent, after its subscription (if any) is accepted.
+struct Session {
+    /// A record is sent if any filter passes it.
+    filters: Vec<RecordFilter>,
+    pace: Option<Pace>,
+    /// Append the send time to every record, as the Live gateway does on request.
+    ts_out: bool,
+    /// Rejected Live subscriptions, sent to the client as error records.
+    errors: Vec<String>,
+}
+
+/// A broadcast client ready for records from the timeline.
+struct Subscriber {
+    peer: SocketAddr,
+    session: Session,
+    outbox: Outbox,
 }
 
+/// Live gateway session IDs.
+static SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-
-    // let path = Arc::new(args.input);
+    let pace_template = Pace {
+        clock: args.clock,
+        speed: args.speed,
+        max_sleep: args.max_sleep_ms.map(Duration::from_millis),
+    };
+    let paths = expand_paths(&args.inputs)?;
+    let first_ts_recv = if args.wall_clock {
+        first_ts_recv(&paths).await?
+    } else {
+        0
+    };
+    let config = Arc::new(Config {
+        pace: args.pace.then_some(pace_template),
+        pace_template,
+        handshake_timeout: Duration::from_millis(args.handshake_timeout_ms),
+        live_key: args.live_key.clone(),
+        broadcast: matches!(args.mode, Mode::Broadcast),
+        outbox: OutboxConfig {
+            capacity: args.queue_bytes,
+            policy: args.slow_consumer,
+            max_lag: Duration::from_millis(args.max_lag_ms),
+        },
+        looping: args.looping,
+        wall_clock: args.wall_clock,
+        first_ts_recv,
+        timeline_offset: OnceLock::new(),
+        zstd: args.zstd,
+    });
+
+    let filter = RecordFilter {
+        instrument_ids: args.instrument_ids.iter().copied().collect(),
+        publisher_ids: args.publisher_ids.iter().copied().collect(),
+        actions: args.actions.iter().copied().collect(),
+        start: args.start,
+        end: args.end,
+    };
 
     match args.mode {
         Mode::Buffered => {
             // 1) Load DBN file into memory: metadata + all MboMsg records
-            let path = &args.input;
-            println!("Loading DBN file: {:?}", path);
+            println!("Loading DBN files: {:?}", paths);
 
-            let (metadata, records) = load_dbn_mbo_file(path).await?;
-            let metadata = Arc::new(metadata);
+            let (metadata, mut records) = load_dbn_mbo_files(&paths).await?;
+            let filter = filter.with_symbols(&metadata, &args.symbols)?;
+            if !filter.is_empty() {
+                records.retain(|mbo| filter.matches(mbo));
+                println!("{} records pass the filter", records.len());
+            }
+            let metadata = Arc::new(filter.metadata(&metadata));
             let records = Arc::new(records);
 
             println!(
@@ -75,22 +280,26 @@ async fn main() -> Result<()> {
 
                 let metadata = Arc::clone(&metadata);
                 let records = Arc::clone(&records);
+                let config = Arc::clone(&config);
 
                 tokio::spawn(async move {
-                    if let Err(err) = handle_client(socket, &metadata, &records).await {
-                        eprintln!("Error serving {}: {err}", peer);
-                    } else {
-                        println!("Finished streaming to {}", peer);
+                    match handle_client(socket, &metadata, &records, &config).await {
+                        Ok(stats) => println!("Finished streaming to {}: {stats}", peer),
+                        Err(err) => eprintln!("Error serving {}: {err}", peer),
                     }
                 });
             }
         }
         Mode::Streaming => {
-            let path = Arc::new(args.input);
+            // Resolve symbols up front so unknown ones fail before any client connects
+            let metadata = MergedDecoder::open(&paths).await?.metadata().clone();
+            let filter = filter.with_symbols(&metadata, &args.symbols)?;
+            let served = Arc::new((filter.metadata(&metadata), filter));
+            let paths = Arc::new(paths);
             let addr: SocketAddr = args.bind.parse()?;
 
             println!("Listening on {}", addr);
-            println!("Streaming DBN file: {:?}", path);
+            println!("Streaming DBN files: {:?}", paths);
 
             let listener = TcpListener::bind(addr).await?;
 
@@ -98,30 +307,86 @@ async fn main() -> Result<()> {
                 let (socket, peer) = listener.accept().await?;
                 println!("New client connected: {}", peer);
 
-                let path = Arc::clone(&path);
+                let paths = Arc::clone(&paths);
+                let served = Arc::clone(&served);
+                let config = Arc::clone(&config);
 
                 tokio::spawn(async move {
-                    if let Err(err) = handle_client_async(socket, &path).await {
-                        eprintln!("Error serving {}: {err}", peer);
-                    } else {
-                        println!("Finished streaming to {}", peer);
+                    let (metadata, filter) = &*served;
+                    match handle_client_async(socket, &paths, metadata, filter, &config).await {
+                        Ok(stats) => println!("Finished streaming to {}: {stats}", peer),
+                        Err(err) => eprintln!("Error serving {}: {err}", peer),
                     }
                 });
             }
         }
+        Mode::Broadcast => {
+            let metadata = MergedDecoder::open(&paths).await?.metadata().clone();
+            let filter = filter.with_symbols(&metadata, &args.symbols)?;
+            let metadata = Arc::new(filter.metadata(&metadata));
+            let addr: SocketAddr = args.bind.parse()?;
+            let listener = TcpListener::bind(addr).await?;
+
+            println!("Listening on {}", addr);
+            println!(
+                "Broadcasting DBN files {:?} once {} clients are connected",
+                paths, args.min_clients
+            );
+
+            let (joined, subscribers) = mpsc::unbounded_channel();
+            let mut timeline = tokio::spawn(run_timeline(
+                paths,
+                filter,
+                Arc::clone(&config),
+                subscribers,
+                args.min_clients,
+                args.snapshot.then(Market::new),
+            ));
+
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => {
+                        let (socket, peer) = accepted?;
+                        println!("New client connected: {}", peer);
+
+                        let joined = joined.clone();
+                        let metadata = Arc::clone(&metadata);
+                        let config = Arc::clone(&config);
+
+                        tokio::spawn(async move {
+                            match join_broadcast(socket, peer, &metadata, &config).await {
+                                // Fails only once the timeline is over
+                                Ok(subscriber) => drop(joined.send(subscriber)),
+                                Err(err) => eprintln!("Error serving {}: {err}", peer),
+                            }
+                        });
+                    }
+                    published = &mut timeline => {
+                        println!("Broadcast {} records", published??);
+                        return Ok(());
+                    }
+                }
+            }
+        }
     }
 }
 
+/// `ts_recv` of the first record across `paths`.
+async fn first_ts_recv(paths: &[PathBuf]) -> Result<u64> {
+    let mut decoder = MergedDecoder::open(paths).await?;
+    Ok(decoder.decode_record().await?.map_or(0, |mbo| mbo.ts_recv))
+}
+
 /// Load metadata + all MBO records from a DBN file into memory.
-async fn load_dbn_mbo_file(path: &Path) -> Result<(Metadata, Vec<MboMsg>)> {
-    let mut decoder = AsyncDbnDecoder::from_file(path).await?;
+async fn load_dbn_mbo_files(paths: &[PathBuf]) -> Result<(Metadata, Vec<MboMsg>)> {
+    let mut decoder = MergedDecoder::open(paths).await?;
 
     // Metadata is parsed first and available via decoder.metadata()
     let metadata = decoder.metadata().clone();
 
     let mut records = Vec::new();
     let mut rec_idx = 0;
-    while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
+    while let Some(mbo) = decoder.decode_record().await? {
         rec_idx += 1;
         // println!("{rec_idx}: {:?}", mbo);
         records.push(mbo.clone());
@@ -137,42 +402,481 @@ async fn load_dbn_mbo_file(path: &Path) -> Result<(Metadata, Vec<MboMsg>)> {
 }
 
 /// Handle a single TCP client: send metadata + all records as DBN.
-async fn handle_client(socket: TcpStream, metadata: &Metadata, records: &[MboMsg]) -> Result<()> {
-    // Creates encoder and writes metadata immediately
-    let mut encoder = AsyncDbnEncoder::new(BufWriter::new(socket), metadata).await?;
-
-    for mbo in records {
-        encoder.encode_record(mbo).await?;
+async fn handle_client(
+    mut socket: TcpStream,
+    metadata: &Metadata,
+    records: &[MboMsg],
+    config: &Config,
+) -> Result<OutboxStats> {
+    let session = open_session(&mut socket, metadata, config).await?;
+
+    // Queues metadata immediately
+    let ts_offset = config.ts_offset();
+    let mut outbox = session
+        .open_outbox(socket, metadata, config, ts_offset)
+        .await?;
+    let mut pacer = session.pacer();
+    let mut rebaser = config.rebaser(ts_offset);
+
+    loop {
+        for mbo in records {
+            if !session.matches(mbo) {
+                continue;
+            }
+            let mbo = rebase(&mut rebaser, mbo);
+            wait_for(&mut pacer, &mbo).await;
+            session.send(&mut outbox, &mbo).await?;
+        }
+        if !session.end_loop(&mut outbox, &mut rebaser, config).await? {
+            break;
+        }
     }
 
-    encoder.flush().await?;
-    encoder.shutdown().await?; // if you want to close the writer nicely
-
-    Ok(())
+    // Waits for the client to receive everything queued
+    outbox.finish().await
 }
 
 /// For each client:
+/// - negotiate its subscription against the `served` metadata
 /// - open the DBN file
 /// - decode metadata + MboMsg records
 /// - encode them to the socket as DBN
-async fn handle_client_async(socket: TcpStream, path: &Path) -> Result<()> {
+async fn handle_client_async(
+    mut socket: TcpStream,
+    paths: &[PathBuf],
+    served: &Metadata,
+    filter: &RecordFilter,
+    config: &Config,
+) -> Result<OutboxStats> {
+    let session = open_session(&mut socket, served, config).await?;
+
     // 1) Open DBN file and create decoder
-    let mut decoder = AsyncDbnDecoder::from_file(path).await?;
+    let mut decoder = MergedDecoder::open(paths).await?;
+
+    // 2) Get metadata, narrowed to what the filters let through
+    let metadata = filter.metadata(decoder.metadata());
+
+    // 3) Queue metadata on the client's outbox
+    let ts_offset = config.ts_offset();
+    let mut outbox = session
+        .open_outbox(socket, &metadata, config, ts_offset)
+        .await?;
+    let mut pacer = session.pacer();
+    let mut rebaser = config.rebaser(ts_offset);
+
+    // 4) Stream all records: decode from file, queue to client, and start over when looping
+    loop {
+        while let Some(mbo) = decoder.decode_record().await? {
+            if !filter.matches(mbo) || !session.matches(mbo) {
+                continue;
+            }
+            let mbo = rebase(&mut rebaser, mbo);
+            wait_for(&mut pacer, &mbo).await;
+            session.send(&mut outbox, &mbo).await?;
+        }
+        if !session.end_loop(&mut outbox, &mut rebaser, config).await? {
+            break;
+        }
+        decoder = MergedDecoder::open(paths).await?;
+    }
 
-    // 2) Get metadata
-    let metadata = decoder.metadata().clone();
+    // 5) Wait for the client to receive everything queued
+    outbox.finish().await
+}
+
+/// Publish every record passing `filter` to all subscribers, starting once `min_clients`
+/// have joined; later subscribers join at the current position, after a snapshot of the
+/// books in `market` if it's maintained. Returns how many records were published.
+async fn run_timeline(
+    paths: Vec<PathBuf>,
+    filter: RecordFilter,
+    config: Arc<Config>,
+    mut joined: mpsc::UnboundedReceiver<Subscriber>,
+    min_clients: usize,
+    mut market: Option<Market>,
+) -> Result<u64> {
+    let mut subscribers = Vec::new();
+    while subscribers.len() < min_clients {
+        match joined.recv().await {
+            Some(subscriber) => subscribers.push(subscriber),
+            None => break,
+        }
+    }
+
+    let mut decoder = MergedDecoder::open(&paths).await?;
+    let mut pacer = config
+        .pace
+        .map(|pace| (pace.clock, Pacer::new(pace.speed, pace.max_sleep)));
+    let mut rebaser = config.rebaser(config.timeline_offset());
+    let mut published = 0;
+    let mut last_ts_recv = 0;
+    loop {
+        while let Some(raw) = decoder.decode_record().await? {
+            let mbo = rebase(&mut rebaser, raw);
+            if !filter.matches(raw) {
+                // Records the server filter drops still change the books snapshots are
+                // taken from
+                if let Some(market) = &mut market {
+                    market.apply(mbo.clone().into_owned());
+                    last_ts_recv = mbo.ts_recv;
+                }
+                continue;
+            }
+            wait_for(&mut pacer, &mbo).await;
+            while let Ok(mut subscriber) = joined.try_recv() {
+                if let Some(market) = &market {
+                    let mut snapshot = market.snapshot_records(last_ts_recv);
+                    snapshot.retain(|mbo| {
+                        filter.matches_book(mbo.hd.instrument_id, mbo.hd.publisher_id)
+                    });
+                    if let Err(err) = subscriber.send_snapshot(&snapshot).await {
+                        eprintln!("Error serving {}: {err}", subscriber.peer);
+                        continue;
+                    }
+                }
+                subscribers.push(subscriber);
+            }
+            if let Some(market) = &mut market {
+                market.apply(mbo.clone().into_owned());
+            }
+            last_ts_recv = mbo.ts_recv;
+            // Like an exchange feed, the timeline keeps going when nobody is listening. With
+            // the block policy, a full queue holds up every subscriber.
+            publish(&mut subscribers, &mbo, |session| session.matches(raw)).await;
+            published += 1;
+        }
+
+        let Some(rebaser) = rebaser
+            .as_mut()
+            .filter(|rebaser| config.looping && !rebaser.is_empty())
+        else {
+            break;
+        };
+        let clears = rebaser.end_loop();
+        for clear in &clears {
+            if let Some(market) = &mut market {
+                market.apply(clear.clone());
+            }
+            last_ts_recv = clear.ts_recv;
+            let book = (clear.hd.instrument_id, clear.hd.publisher_id);
+            if !filter.matches_book(book.0, book.1) {
+                continue;
+            }
+            publish(&mut subscribers, clear, |session| {
+                session.matches_book(book.0, book.1)
+            })
+            .await;
+        }
+        println!("Finished loop {}, starting over", rebaser.loops());
+        decoder = MergedDecoder::open(&paths).await?;
+    }
+
+    let mut finishing = JoinSet::new();
+    for Subscriber { peer, outbox, .. } in subscribers {
+        finishing.spawn(async move {
+            match outbox.finish().await {
+                Ok(stats) => println!("Finished streaming to {}: {stats}", peer),
+                Err(err) => eprintln!("Error serving {}: {err}", peer),
+            }
+        });
+    }
+    while finishing.join_next().await.is_some() {}
+    Ok(published)
+}
+
+/// Queue `mbo` for every subscriber `matches` accepts, dropping those whose outbox failed.
+async fn publish(
+    subscribers: &mut Vec<Subscriber>,
+    mbo: &MboMsg,
+    matches: impl Fn(&Session) -> bool,
+) {
+    let mut idx = 0;
+    while idx < subscribers.len() {
+        let Subscriber {
+            peer,
+            session,
+            outbox,
+        } = &mut subscribers[idx];
+        if !matches(session) {
+            idx += 1;
+        } else if let Err(err) = session.send(outbox, mbo).await {
+            eprintln!("Error serving {}: {err}", peer);
+            subscribers.swap_remove(idx);
+        } else {
+            idx += 1;
+        }
+    }
+}
+
+impl Subscriber {
+    /// Queue the part of `snapshot` the client subscribed to, one chunk per book so the
+    /// slow consumer policy drops whole books.
+    async fn send_snapshot(&mut self, snapshot: &[MboMsg]) -> Result<()> {
+        let books = snapshot.chunk_by(|a, b| {
+            (a.hd.instrument_id, a.hd.publisher_id) == (b.hd.instrument_id, b.hd.publisher_id)
+        });
+        for book in books {
+            let (instrument_id, publisher_id) = (book[0].hd.instrument_id, book[0].hd.publisher_id);
+            if !self.session.matches_book(instrument_id, publisher_id) {
+                continue;
+            }
+            let bytes = book
+                .iter()
+                .flat_map(|mbo| self.session.encode(mbo))
+                .collect();
+            self.outbox.push(bytes, book.len() as u64).await?;
+        }
+        Ok(())
+    }
+}
 
-    // 3) Create encoder on the socket; this writes metadata immediately
-    let mut encoder = AsyncDbnEncoder::new(BufWriter::new(socket), &metadata).await?;
+/// Negotiate a broadcast client's subscription and queue its metadata, ready to join the
+/// timeline.
+async fn join_broadcast(
+    mut socket: TcpStream,
+    peer: SocketAddr,
+    metadata: &Metadata,
+    config: &Config,
+) -> Result<Subscriber> {
+    let session = open_session(&mut socket, metadata, config).await?;
+    let outbox = session
+        .open_outbox(socket, metadata, config, config.timeline_offset())
+        .await?;
+    Ok(Subscriber {
+        peer,
+        session,
+        outbox,
+    })
+}
 
-    // 4) Stream all records: decode from file, encode to client
-    while let Some(mbo) = decoder.decode_record::<MboMsg>().await? {
-        encoder.encode_record(mbo).await?;
+/// `mbo` moved into the current loop, if rebasing.
+fn rebase<'a>(rebaser: &mut Option<Rebaser>, mbo: &'a MboMsg) -> Cow<'a, MboMsg> {
+    match rebaser {
+        Some(rebaser) => Cow::Owned(rebaser.rebase(mbo)),
+        None => Cow::Borrowed(mbo),
     }
+}
+
+/// With pacing on, sleep until `mbo` is due.
+async fn wait_for(pacer: &mut Option<(PaceClock, Pacer)>, mbo: &MboMsg) {
+    let Some((clock, pacer)) = pacer else {
+        return;
+    };
+    if let Some(deadline) = pacer.deadline(clock.ts(mbo)) {
+        time::sleep_until(deadline).await;
+    }
+}
 
-    // 5) Flush (and optionally shutdown)
-    encoder.flush().await?;
-    // encoder.shutdown().await?; // if the API provides it, nice to call
+/// Read the client's subscription and accept or reject it. Clients that send nothing within
+/// the handshake timeout are legacy clients and get the server's defaults.
+async fn open_session(
+    socket: &mut TcpStream,
+    metadata: &Metadata,
+    config: &Config,
+) -> Result<Session> {
+    if let Some(key) = &config.live_key {
+        return open_live_session(socket, metadata, config, key).await;
+    }
+    let mut first = [0; 1];
+    match time::timeout(config.handshake_timeout, socket.peek(&mut first)).await {
+        Err(_) => {
+            return Ok(Session {
+                filters: vec![RecordFilter::default()],
+                pace: config.pace,
+                ts_out: false,
+                errors: Vec::new(),
+            })
+        }
+        Ok(read) => {
+            if read? == 0 {
+                anyhow::bail!("client closed the connection");
+            }
+        }
+    }
 
-    Ok(())
+    let line = read_line(socket).await?;
+    let session = line.parse::<Subscription>().and_then(|subscription| {
+        if config.broadcast && subscription.speed.is_some() {
+            anyhow::bail!("speed is fixed for every client in broadcast mode");
+        }
+        let pace = match subscription.speed {
+            None => config.pace,
+            Some(Speed::Max) => None,
+            Some(speed) => Some(Pace {
+                speed,
+                ..config.pace_template
+            }),
+        };
+        Ok(Session {
+            filters: vec![subscription.filter(metadata)?],
+            pace,
+            ts_out: false,
+            errors: Vec::new(),
+        })
+    });
+    match session {
+        Ok(session) => {
+            println!("Accepted {line:?}");
+            write_line(socket, &Reply::Ok.to_string()).await?;
+            Ok(session)
+        }
+        Err(err) => {
+            write_line(socket, &Reply::Err(err.to_string()).to_string()).await?;
+            Err(anyhow::anyhow!("rejected {line:?}: {err}"))
+        }
+    }
+}
+
+/// Authenticate a Live protocol client and turn its subscriptions into filters. Subscriptions
+/// that can't be served are reported to the client as error records, like the gateway does.
+async fn open_live_session(
+    socket: &mut TcpStream,
+    metadata: &Metadata,
+    config: &Config,
+    key: &str,
+) -> Result<Session> {
+    let session_id = SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    let live = gateway::accept(socket, key, &metadata.dataset, session_id).await?;
+    let mut filters = Vec::new();
+    let mut errors = Vec::new();
+    for subscription in &live.subscriptions {
+        match subscription.filter(metadata) {
+            Ok(filter) => filters.push(filter),
+            Err(err) => errors.push(format!("Subscription rejected: {err}")),
+        }
+    }
+    println!(
+        "Live session {session_id}: {} subscriptions, {} rejected",
+        live.subscriptions.len(),
+        errors.len()
+    );
+    Ok(Session {
+        filters,
+        pace: config.pace,
+        ts_out: live.ts_out,
+        errors,
+    })
+}
+
+impl Session {
+    fn matches(&self, mbo: &MboMsg) -> bool {
+        self.filters.iter().any(|filter| filter.matches(mbo))
+    }
+
+    fn matches_book(&self, instrument_id: u32, publisher_id: u16) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| filter.matches_book(instrument_id, publisher_id))
+    }
+
+    /// Hand `socket` to an outbox with the session's `metadata` and any subscription errors
+    /// queued. The metadata's time range is moved by `ts_offset`, like the records.
+    async fn open_outbox(
+        &self,
+        socket: TcpStream,
+        metadata: &Metadata,
+        config: &Config,
+        ts_offset: u64,
+    ) -> Result<Outbox> {
+        let mut metadata = if self.filters.is_empty() {
+            // Every Live subscription was rejected, so no instrument will be sent
+            Metadata {
+                symbols: Vec::new(),
+                partial: Vec::new(),
+                not_found: Vec::new(),
+                mappings: Vec::new(),
+                ..metadata.clone()
+            }
+        } else {
+            RecordFilter::union(&self.filters).metadata(metadata)
+        };
+        metadata.ts_out = self.ts_out;
+        metadata.start += ts_offset;
+        metadata.end = metadata
+            .end
+            .filter(|_| !config.looping)
+            .and_then(|end| end.checked_add(ts_offset));
+        let mut header = Vec::new();
+        DbnMetadataEncoder::new(&mut header).encode(&metadata)?;
+
+        let socket = compressed(socket, config.zstd);
+        let mut outbox = Outbox::spawn(socket, config.outbox, metadata.version, self.ts_out);
+        outbox.push(header, 0).await?;
+        for (idx, err) in self.errors.iter().enumerate() {
+            let error = ErrorMsg::new(now_ns(), None, err, idx + 1 == self.errors.len());
+            // Never dropped, like the metadata
+            let bytes = if self.ts_out {
+                WithTsOut::new(error, now_ns()).as_ref().to_vec()
+            } else {
+                error.as_ref().to_vec()
+            };
+            outbox.push(bytes, 0).await?;
+        }
+        Ok(outbox)
+    }
+
+    async fn send(&self, outbox: &mut Outbox, mbo: &MboMsg) -> Result<()> {
+        outbox.push(self.encode(mbo), 1).await
+    }
+
+    fn encode(&self, mbo: &MboMsg) -> Vec<u8> {
+        if self.ts_out {
+            WithTsOut::new(mbo.clone(), now_ns()).as_ref().to_vec()
+        } else {
+            mbo.as_ref().to_vec()
+        }
+    }
+
+    /// With looping on, queue the Clears ending a loop and return whether to replay the file
+    /// again. A loop that sent nothing isn't repeated.
+    async fn end_loop(
+        &self,
+        outbox: &mut Outbox,
+        rebaser: &mut Option<Rebaser>,
+        config: &Config,
+    ) -> Result<bool> {
+        match rebaser {
+            Some(rebaser) if config.looping && !rebaser.is_empty() => {
+                for clear in rebaser.end_loop() {
+                    self.send(outbox, &clear).await?;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn pacer(&self) -> Option<(PaceClock, Pacer)> {
+        self.pace
+            .map(|pace| (pace.clock, Pacer::new(pace.speed, pace.max_sleep)))
+    }
+}
+
+impl Config {
+    /// How far the wall clock moves the timestamps of a replay starting now; 0 without it.
+    fn ts_offset(&self) -> u64 {
+        if self.wall_clock {
+            now_ns().saturating_sub(self.first_ts_recv)
+        } else {
+            0
+        }
+    }
+
+    /// [`Config::ts_offset`] of the broadcast timeline, which every subscriber shares.
+    fn timeline_offset(&self) -> u64 {
+        *self.timeline_offset.get_or_init(|| self.ts_offset())
+    }
+
+    /// A fresh [`Rebaser`] for one replay, if timestamps are rebased.
+    fn rebaser(&self, ts_offset: u64) -> Option<Rebaser> {
+        (self.looping || self.wall_clock).then(|| Rebaser::new(ts_offset))
+    }
+}
+
+fn parse_live_key(key: &str) -> Result<String, String> {
+    if key.len() == 32 && key.is_ascii() {
+        Ok(key.to_owned())
+    } else {
+        Err("expected a 32-character ASCII API key".to_owned())
+    }
 }
@@ -0,0 +1,142 @@
+use ::mbo_orderbook::{
+    compression::file_decoder,
+    orderbook::Market,
+    verify::{BookVerifyReport, Level, LevelMismatch, VendorBook, Verifier},
+};
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use clap::Parser;
+
+use databento::dbn::{pretty, MboMsg};
+use serde::Serialize;
+
+/// Replay an MBO DBN file alongside vendor MBP-10/MBP-1/BBO data and report book mismatches.
+#[derive(Parser, Debug)]
+#[command(
+    name = "mbo-verify",
+    version,
+    about = "Cross-validate reconstructed MBO books against vendor MBP-10/BBO data (DBN)",
+    long_about = None
+)]
+struct Args {
+    /// Path to the MBO DBN file
+    #[arg(value_name = "MBO_FILE")]
+    mbo: PathBuf,
+
+    /// Path to the MBP-10, MBP-1/TBBO or BBO DBN file for the same instruments and time range
+    #[arg(value_name = "VENDOR_FILE")]
+    vendor: PathBuf,
+
+    /// Number of mismatched levels to print (0 = none)
+    #[arg(long, short = 'n', default_value_t = 20)]
+    examples: usize,
+
+    /// Don't compare order counts
+    #[arg(long)]
+    ignore_counts: bool,
+
+    /// Write the report as JSON to this path
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Exit with an error if any mismatch is found
+    #[arg(long)]
+    deny: bool,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    vendor_records: u64,
+    compared: u64,
+    mismatched: u64,
+    books: &'a [BookVerifyReport],
+    first_mismatches: &'a [LevelMismatch],
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let mut mbo_decoder = file_decoder(&args.mbo).await?;
+    let mut vendor_decoder = file_decoder(&args.vendor).await?;
+    let mut market = Market::new();
+    let mut verifier = Verifier::new(!args.ignore_counts);
+
+    let mut pending = mbo_decoder.decode_record::<MboMsg>().await?.cloned();
+    let mut vendor_records = 0;
+    let mut examples = Vec::new();
+    while let Some(rec) = vendor_decoder.decode_record_ref().await? {
+        let Some(vendor) = VendorBook::from_record(rec) else {
+            continue;
+        };
+        vendor_records += 1;
+        // Catch the book up to the vendor record
+        while let Some(mbo) = pending.take_if(|mbo| vendor.includes(mbo.ts_recv, mbo.sequence)) {
+            market.apply(mbo);
+            pending = mbo_decoder.decode_record::<MboMsg>().await?.cloned();
+        }
+        if !vendor.is_comparable() {
+            continue;
+        }
+        for mismatch in verifier.check(&market, &vendor) {
+            if examples.len() < args.examples {
+                println!(
+                    "ts_recv={} seq={} instr_id={} publisher_id={} level={}",
+                    mismatch.ts_recv,
+                    mismatch.sequence,
+                    mismatch.instrument_id,
+                    mismatch.publisher_id,
+                    mismatch.level
+                );
+                println!("  ours:   {}", fmt_level(&mismatch.ours));
+                println!("  theirs: {}", fmt_level(&mismatch.theirs));
+                examples.push(mismatch);
+            }
+        }
+    }
+
+    let books = verifier.report();
+    let compared: u64 = books.iter().map(|book| book.stats.compared).sum();
+    let mismatched: u64 = books.iter().map(|book| book.stats.mismatched).sum();
+    if vendor_records == 0 {
+        anyhow::bail!("no MBP-10, MBP-1 or BBO records in {:?}", args.vendor);
+    }
+    println!("\nCompared {compared} vendor books, {mismatched} mismatched");
+    for book in &books {
+        println!(
+            "instr_id={} publisher_id={} compared={} mismatched={} missing_books={}",
+            book.instrument_id,
+            book.publisher_id,
+            book.stats.compared,
+            book.stats.mismatched,
+            book.stats.missing_books
+        );
+    }
+    if let Some(path) = &args.output {
+        let report = Report {
+            vendor_records,
+            compared,
+            mismatched,
+            books: &books,
+            first_mismatches: &examples,
+        };
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &report)?;
+        println!("Wrote report to {:?}", path);
+    }
+    if args.deny && mismatched > 0 {
+        anyhow::bail!("{mismatched} vendor books disagree with the reconstruction");
+    }
+
+    Ok(())
+}
+
+fn fmt_level(level: &Level) -> String {
+    format!(
+        "bid {:>4} @ {:.2} ({:>2}) | ask {:>4} @ {:.2} ({:>2})",
+        level.bid_sz,
+        pretty::Px(level.bid_px),
+        level.bid_ct,
+        level.ask_sz,
+        pretty::Px(level.ask_px),
+        level.ask_ct
+    )
+}
@@ -1,4 +1,14 @@
-use databento::dbn::{pretty, Action, MboMsg, Side};
+use std::{
+    collections::HashMap,
+    ffi::c_char,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use databento::dbn::{
+    decode::{DbnDecoder, DecodeRecordRef},
+    pretty, rtype, Action, FlagSet, MboMsg, Metadata, RecordHeader, SType, Side, FIXED_PRICE_SCALE,
+    UNDEF_PRICE,
+};
 
 /// Pretty-print a single MBO record.
 pub fn print_pretty(idx: usize, mbo: &MboMsg) {
@@ -25,3 +35,98 @@ pub fn print_pretty(idx: usize, mbo: &MboMsg) {
     //     idx, pretty::Ts(mbo.hd.ts_event), mbo.hd.instrument_id, mbo.order_id, pretty::Px(mbo.price), mbo.size, side, action,
     // );
 }
+
+/// A fixed-precision price in price units.
+pub fn to_px(price: i64) -> f64 {
+    price as f64 / FIXED_PRICE_SCALE as f64
+}
+
+/// The current time in UNIX nanoseconds.
+pub fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
+/// Map instrument IDs to raw symbols from the DBN metadata symbology.
+pub fn instrument_symbols(metadata: &Metadata) -> HashMap<u32, String> {
+    let mut symbols = HashMap::new();
+    for mapping in &metadata.mappings {
+        for interval in &mapping.intervals {
+            // When the input symbology is instrument IDs, `raw_symbol` holds the ID instead
+            let (id, symbol) = if metadata.stype_in == Some(SType::InstrumentId) {
+                (&mapping.raw_symbol, &interval.symbol)
+            } else {
+                (&interval.symbol, &mapping.raw_symbol)
+            };
+            if let Ok(id) = id.parse() {
+                symbols.insert(id, symbol.clone());
+            }
+        }
+    }
+    symbols
+}
+
+/// A synthetic Clear of the book of `instrument_id` and `publisher_id` at `ts`, used for both
+/// `ts_event` and `ts_recv`.
+pub fn clear_record(
+    instrument_id: u32,
+    publisher_id: u16,
+    ts: u64,
+    flags: FlagSet,
+    sequence: u32,
+) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, publisher_id, instrument_id, ts),
+        order_id: 0,
+        price: UNDEF_PRICE,
+        size: 0,
+        flags,
+        channel_id: 0,
+        action: Action::Clear as u8 as c_char,
+        side: Side::None as u8 as c_char,
+        ts_recv: ts,
+        ts_in_delta: 0,
+        sequence,
+    }
+}
+
+/// Read one MBO record from the start of raw DBN record bytes, which needn't be aligned.
+pub fn mbo_from_bytes(bytes: &[u8]) -> Option<MboMsg> {
+    let len = size_of::<MboMsg>();
+    if bytes.len() < len || bytes[0] as usize * 4 != len || bytes[1] != rtype::MBO {
+        return None;
+    }
+    // SAFETY: `MboMsg` is a `repr(C)` record valid for any bit pattern and `bytes` holds
+    // at least `size_of::<MboMsg>()` bytes
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast::<MboMsg>()) })
+}
+
+/// Decode the MBO records in either a DBN stream (with metadata) or concatenated raw DBN
+/// records, skipping other record types.
+pub fn mbo_records(data: &[u8]) -> anyhow::Result<Vec<MboMsg>> {
+    let mut records = Vec::new();
+    if data.starts_with(b"DBN") {
+        let mut decoder = DbnDecoder::new(data)?;
+        while let Some(rec) = decoder.decode_record_ref()? {
+            if let Some(mbo) = rec.get::<MboMsg>() {
+                records.push(mbo.clone());
+            }
+        }
+        return Ok(records);
+    }
+    let mut rest = data;
+    while !rest.is_empty() {
+        let len = rest[0] as usize * 4;
+        if len == 0 || len > rest.len() {
+            anyhow::bail!("truncated DBN record at byte {}", data.len() - rest.len());
+        }
+        if rest[1] == rtype::MBO {
+            let mbo = mbo_from_bytes(&rest[..len])
+                .ok_or_else(|| anyhow::anyhow!("invalid MBO record length {len}"))?;
+            records.push(mbo);
+        }
+        rest = &rest[len..];
+    }
+    Ok(records)
+}
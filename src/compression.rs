@@ -0,0 +1,60 @@
+//! Zstd-compressed DBN, told apart from plain DBN by its first bytes.
+
+use std::{io, path::Path};
+
+use async_compression::tokio::write::ZstdEncoder;
+use databento::dbn::decode::{zstd, AsyncDbnDecoder};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, BufReader},
+};
+
+/// A DBN byte stream, decompressed if it arrived compressed.
+pub type DbnReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// A byte sink, compressing if asked to.
+pub type DbnWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// `reader` decompressed if it starts with a zstd frame, otherwise as is.
+pub async fn decompressed<R>(mut reader: R) -> io::Result<DbnReader>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let mut prefix = Vec::with_capacity(4);
+    (&mut reader).take(4).read_to_end(&mut prefix).await?;
+    let is_zstd = zstd::starts_with_prefix(&prefix);
+    let reader = BufReader::new(io::Cursor::new(prefix).chain(reader));
+    Ok(if is_zstd {
+        Box::new(zstd::zstd_decoder(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Open a DBN file for decoding, zstd-compressed (`.dbn.zst`) or not.
+pub async fn file_decoder(
+    path: impl AsRef<Path>,
+) -> databento::dbn::Result<AsyncDbnDecoder<DbnReader>> {
+    let path = path.as_ref();
+    let context = || format!("opening DBN file at path '{}'", path.display());
+    let file = File::open(path)
+        .await
+        .map_err(|err| databento::dbn::Error::io(err, context()))?;
+    let reader = decompressed(file)
+        .await
+        .map_err(|err| databento::dbn::Error::io(err, context()))?;
+    AsyncDbnDecoder::new(reader).await
+}
+
+/// `writer`, compressing everything written to it with zstd if `zstd` is set. Each flush
+/// ends a zstd block, so a reader can decode everything written up to it.
+pub fn compressed<W>(writer: W, zstd: bool) -> DbnWriter
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    if zstd {
+        Box::new(ZstdEncoder::new(writer))
+    } else {
+        Box::new(writer)
+    }
+}
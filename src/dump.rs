@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use databento::dbn::{MboMsg, Side};
+use serde::Serialize;
+
+use crate::{
+    common::to_px,
+    orderbook::{Book, Market, PriceLevel},
+};
+
+/// All books keyed by instrument ID then publisher ID, in the JSON shape of the Python
+/// `Market.to_dict()` in `mbo_tools/order_book.py`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct MarketDump(pub BTreeMap<String, BTreeMap<String, BookDump>>);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BookDump {
+    /// Best first.
+    pub bids: Vec<LevelDump>,
+    pub asks: Vec<LevelDump>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelDump {
+    pub price: i64,
+    pub size: u32,
+    pub count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orders: Option<Vec<OrderDump>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderDump {
+    pub order_id: u64,
+    pub price: i64,
+    pub pretty_price: f64,
+    pub size: u32,
+    pub side: String,
+    pub action: String,
+    pub flags: u8,
+    pub instrument_id: u32,
+    pub publisher_id: u16,
+}
+
+impl MarketDump {
+    /// Like Python's `Market.to_dict(include_orders=...)`.
+    pub fn new(market: &Market, include_orders: bool) -> Self {
+        let mut books: BTreeMap<String, BTreeMap<String, BookDump>> = BTreeMap::new();
+        for (instrument_id, publisher, book) in market.books() {
+            books.entry(instrument_id.to_string()).or_default().insert(
+                (publisher as u16).to_string(),
+                BookDump::new(book, include_orders),
+            );
+        }
+        Self(books)
+    }
+}
+
+impl BookDump {
+    pub fn new(book: &Book, include_orders: bool) -> Self {
+        let level = |side: Side, level: PriceLevel| LevelDump {
+            price: level.price,
+            size: level.size,
+            count: level.count,
+            orders: include_orders.then(|| {
+                book.level_orders(side, level.price)
+                    .map(OrderDump::new)
+                    .collect()
+            }),
+        };
+        Self {
+            bids: book.bid_levels().map(|l| level(Side::Bid, l)).collect(),
+            asks: book.ask_levels().map(|l| level(Side::Ask, l)).collect(),
+        }
+    }
+}
+
+impl OrderDump {
+    pub fn new(order: &MboMsg) -> Self {
+        Self {
+            order_id: order.order_id,
+            price: order.price,
+            pretty_price: to_px(order.price),
+            size: order.size,
+            side: (order.side as u8 as char).to_string(),
+            action: (order.action as u8 as char).to_string(),
+            flags: order.flags.raw(),
+            instrument_id: order.hd.instrument_id,
+            publisher_id: order.hd.publisher_id,
+        }
+    }
+}
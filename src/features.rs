@@ -0,0 +1,496 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use databento::dbn::{Action, MboMsg, Side};
+use serde::Serialize;
+
+use crate::{metrics::Depth, orderbook::Market, orderbook::PriceLevel};
+
+/// When a [`FeatureRow`] is emitted for an instrument.
+#[derive(Debug, Clone, Copy)]
+pub enum Clock {
+    /// After every event (record flagged `F_LAST`).
+    Event,
+    /// Every `interval_ns` of `ts_recv`, at least 1; intervals in which no record arrived are
+    /// skipped.
+    Time { interval_ns: u64 },
+    /// Every `volume` units traded.
+    Volume { volume: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct FeatureConfig {
+    /// Depths (number of levels) OFI is computed over, e.g. `[1, 5, 10]`.
+    pub depths: Vec<usize>,
+    pub clock: Clock,
+}
+
+/// Event-based features for one instrument over one clock interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureRow {
+    pub ts_recv: u64,
+    pub instrument_id: u32,
+    pub duration_ns: u64,
+    /// Consolidated mid at the end of the interval.
+    pub mid: Option<f64>,
+    /// Integrated order flow imbalance, one value per configured depth.
+    pub ofi: Vec<f64>,
+    /// Trade volume signed by aggressor side, buys positive.
+    pub signed_volume: i64,
+    pub trade_volume: u64,
+    pub trades: u64,
+    pub bid_adds: u64,
+    pub ask_adds: u64,
+    pub bid_cancels: u64,
+    pub ask_cancels: u64,
+}
+
+/// Computes [`FeatureRow`]s incrementally as records are applied to a [`Market`].
+#[derive(Debug)]
+pub struct FeatureTracker {
+    config: FeatureConfig,
+    max_depth: usize,
+    instruments: HashMap<u32, InstrumentFeatures>,
+    // Start of the current interval for `Clock::Time`
+    bucket_start: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct InstrumentFeatures {
+    bids: Vec<PriceLevel>,
+    asks: Vec<PriceLevel>,
+    mid: Option<f64>,
+    start_ts: u64,
+    last_ts: u64,
+    ofi: Vec<f64>,
+    signed_volume: i64,
+    trade_volume: u64,
+    trades: u64,
+    bid_adds: u64,
+    ask_adds: u64,
+    bid_cancels: u64,
+    ask_cancels: u64,
+}
+
+pub enum FeatureWriter {
+    Csv(BufWriter<File>),
+    #[cfg(feature = "arrow")]
+    Arrow(Box<arrow::ArrowFeatureWriter>),
+}
+
+impl FeatureRow {
+    /// Cancels per trade, `None` without trades.
+    pub fn cancel_to_trade(&self) -> Option<f64> {
+        (self.trades > 0).then(|| (self.bid_cancels + self.ask_cancels) as f64 / self.trades as f64)
+    }
+
+    /// Events per second over the interval, `None` for zero-length intervals.
+    pub fn intensity(&self, count: u64) -> Option<f64> {
+        (self.duration_ns > 0).then(|| count as f64 * 1e9 / self.duration_ns as f64)
+    }
+}
+
+impl FeatureTracker {
+    pub fn new(mut config: FeatureConfig) -> Self {
+        if let Clock::Time { interval_ns } = &mut config.clock {
+            *interval_ns = (*interval_ns).max(1);
+        }
+        config.depths.retain(|depth| *depth > 0);
+        config.depths.sort_unstable();
+        config.depths.dedup();
+        let max_depth = config.depths.iter().copied().max().unwrap_or(1);
+        Self {
+            config,
+            max_depth,
+            instruments: HashMap::new(),
+            bucket_start: None,
+        }
+    }
+
+    pub fn config(&self) -> &FeatureConfig {
+        &self.config
+    }
+
+    /// Update features with `mbo`, which must already be applied to `market`.
+    ///
+    /// Returns the rows completed by this record, if any.
+    pub fn update(&mut self, market: &Market, mbo: &MboMsg) -> Vec<FeatureRow> {
+        let mut rows = Vec::new();
+        if let Clock::Time { interval_ns } = self.config.clock {
+            let bucket = mbo.ts_recv - mbo.ts_recv % interval_ns;
+            match self.bucket_start {
+                Some(start) if bucket > start => {
+                    rows.extend(self.flush_all(start + interval_ns));
+                    self.bucket_start = Some(bucket);
+                }
+                None => self.bucket_start = Some(bucket),
+                _ => {}
+            }
+        }
+
+        let depths_len = self.config.depths.len();
+        let state = self
+            .instruments
+            .entry(mbo.hd.instrument_id)
+            .or_insert_with(|| InstrumentFeatures {
+                ofi: vec![0.0; depths_len],
+                start_ts: mbo.ts_recv,
+                ..Default::default()
+            });
+        state.last_ts = mbo.ts_recv;
+        let side = mbo.side().unwrap_or(Side::None);
+        match mbo.action() {
+            Ok(Action::Add) => match side {
+                Side::Bid => state.bid_adds += 1,
+                Side::Ask => state.ask_adds += 1,
+                Side::None => {}
+            },
+            Ok(Action::Cancel) => match side {
+                Side::Bid => state.bid_cancels += 1,
+                Side::Ask => state.ask_cancels += 1,
+                Side::None => {}
+            },
+            Ok(Action::Trade) => {
+                state.trades += 1;
+                state.trade_volume += mbo.size as u64;
+                match side {
+                    Side::Bid => state.signed_volume += mbo.size as i64,
+                    Side::Ask => state.signed_volume -= mbo.size as i64,
+                    Side::None => {}
+                }
+            }
+            _ => {}
+        }
+
+        // Intermediate book states inside an event aren't meaningful for OFI
+        if !mbo.flags.is_last() {
+            return rows;
+        }
+        let depth = Depth::from_market(market, mbo.hd.instrument_id, self.max_depth);
+        let mut ofi = 0.0;
+        let mut depth_idx = 0;
+        for level in 0..self.max_depth {
+            ofi += level_ofi(
+                state.bids.get(level),
+                depth.bids.get(level),
+                state.asks.get(level),
+                depth.asks.get(level),
+            );
+            while depth_idx < depths_len && self.config.depths[depth_idx] == level + 1 {
+                state.ofi[depth_idx] += ofi;
+                depth_idx += 1;
+            }
+        }
+        state.mid = depth.mid();
+        state.bids = depth.bids;
+        state.asks = depth.asks;
+
+        let emit = match self.config.clock {
+            Clock::Event => true,
+            Clock::Volume { volume } => state.trade_volume >= volume,
+            Clock::Time { .. } => false,
+        };
+        if emit {
+            rows.push(state.take_row(mbo.hd.instrument_id, mbo.ts_recv));
+        }
+        rows
+    }
+
+    /// Emit the partial interval of every instrument, e.g. at the end of a replay.
+    pub fn flush(&mut self) -> Vec<FeatureRow> {
+        let end_ts = self
+            .instruments
+            .values()
+            .map(|state| state.last_ts)
+            .max()
+            .unwrap_or_default();
+        self.flush_all(end_ts)
+    }
+
+    fn flush_all(&mut self, ts: u64) -> Vec<FeatureRow> {
+        let mut rows: Vec<FeatureRow> = self
+            .instruments
+            .iter_mut()
+            .map(|(instrument_id, state)| state.take_row(*instrument_id, ts))
+            .collect();
+        rows.sort_by_key(|row| row.instrument_id);
+        rows
+    }
+}
+
+impl InstrumentFeatures {
+    fn take_row(&mut self, instrument_id: u32, ts: u64) -> FeatureRow {
+        let row = FeatureRow {
+            ts_recv: ts,
+            instrument_id,
+            duration_ns: ts.saturating_sub(self.start_ts),
+            mid: self.mid,
+            ofi: self.ofi.clone(),
+            signed_volume: self.signed_volume,
+            trade_volume: self.trade_volume,
+            trades: self.trades,
+            bid_adds: self.bid_adds,
+            ask_adds: self.ask_adds,
+            bid_cancels: self.bid_cancels,
+            ask_cancels: self.ask_cancels,
+        };
+        // Keep the book state OFI is measured against, reset the counters
+        self.ofi.iter_mut().for_each(|ofi| *ofi = 0.0);
+        self.signed_volume = 0;
+        self.trade_volume = 0;
+        self.trades = 0;
+        self.bid_adds = 0;
+        self.ask_adds = 0;
+        self.bid_cancels = 0;
+        self.ask_cancels = 0;
+        self.start_ts = ts;
+        row
+    }
+}
+
+/// Order flow imbalance contribution of one level (Cont, Kukanov & Stoikov).
+fn level_ofi(
+    prev_bid: Option<&PriceLevel>,
+    bid: Option<&PriceLevel>,
+    prev_ask: Option<&PriceLevel>,
+    ask: Option<&PriceLevel>,
+) -> f64 {
+    // Flow into the bid when it improves, out of it when it backs off; mirrored for the ask
+    let bid_flow = match (prev_bid, bid) {
+        (Some(prev), Some(cur)) if cur.price > prev.price => cur.size as f64,
+        (Some(prev), Some(cur)) if cur.price == prev.price => cur.size as f64 - prev.size as f64,
+        (Some(prev), _) => -(prev.size as f64),
+        (None, Some(cur)) => cur.size as f64,
+        (None, None) => 0.0,
+    };
+    let ask_flow = match (prev_ask, ask) {
+        (Some(prev), Some(cur)) if cur.price < prev.price => cur.size as f64,
+        (Some(prev), Some(cur)) if cur.price == prev.price => cur.size as f64 - prev.size as f64,
+        (Some(prev), _) => -(prev.size as f64),
+        (None, Some(cur)) => cur.size as f64,
+        (None, None) => 0.0,
+    };
+    bid_flow - ask_flow
+}
+
+impl FeatureWriter {
+    /// Create a CSV writer, or an Arrow IPC file writer for `.arrow`/`.feather` paths.
+    pub fn create(path: impl AsRef<Path>, depths: &[usize]) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let is_arrow = path
+            .extension()
+            .is_some_and(|ext| ext == "arrow" || ext == "feather");
+        if is_arrow {
+            #[cfg(feature = "arrow")]
+            return Ok(Self::Arrow(Box::new(arrow::ArrowFeatureWriter::create(
+                path, depths,
+            )?)));
+            #[cfg(not(feature = "arrow"))]
+            anyhow::bail!("writing Arrow files requires the `arrow` feature");
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_csv_header(&mut writer, depths)?;
+        Ok(Self::Csv(writer))
+    }
+
+    pub fn write(&mut self, row: &FeatureRow) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(writer) => write_csv_row(writer, row)?,
+            #[cfg(feature = "arrow")]
+            Self::Arrow(writer) => writer.write(row)?,
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(mut writer) => writer.flush()?,
+            #[cfg(feature = "arrow")]
+            Self::Arrow(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+const COUNT_COLUMNS: [&str; 12] = [
+    "signed_volume",
+    "trade_volume",
+    "trades",
+    "bid_adds",
+    "ask_adds",
+    "bid_cancels",
+    "ask_cancels",
+    "bid_add_intensity",
+    "ask_add_intensity",
+    "bid_cancel_intensity",
+    "ask_cancel_intensity",
+    "cancel_to_trade",
+];
+
+pub fn write_csv_header(mut writer: impl Write, depths: &[usize]) -> io::Result<()> {
+    write!(writer, "ts_recv,instrument_id,duration_ns,mid")?;
+    for depth in depths {
+        write!(writer, ",ofi_{depth}")?;
+    }
+    for column in COUNT_COLUMNS {
+        write!(writer, ",{column}")?;
+    }
+    writeln!(writer)
+}
+
+pub fn write_csv_row(mut writer: impl Write, row: &FeatureRow) -> io::Result<()> {
+    let opt = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+    write!(
+        writer,
+        "{},{},{},{}",
+        row.ts_recv,
+        row.instrument_id,
+        row.duration_ns,
+        opt(row.mid)
+    )?;
+    for ofi in &row.ofi {
+        write!(writer, ",{ofi}")?;
+    }
+    writeln!(
+        writer,
+        ",{},{},{},{},{},{},{},{},{},{},{},{}",
+        row.signed_volume,
+        row.trade_volume,
+        row.trades,
+        row.bid_adds,
+        row.ask_adds,
+        row.bid_cancels,
+        row.ask_cancels,
+        opt(row.intensity(row.bid_adds)),
+        opt(row.intensity(row.ask_adds)),
+        opt(row.intensity(row.bid_cancels)),
+        opt(row.intensity(row.ask_cancels)),
+        opt(row.cancel_to_trade()),
+    )
+}
+
+#[cfg(feature = "arrow")]
+mod arrow {
+    use std::{fs::File, path::Path, sync::Arc};
+
+    use arrow_array::{
+        builder::{Float64Builder, Int64Builder, UInt32Builder, UInt64Builder},
+        ArrayRef, RecordBatch,
+    };
+    use arrow_ipc::writer::FileWriter;
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::{FeatureRow, COUNT_COLUMNS};
+
+    const BATCH_ROWS: usize = 64 * 1024;
+
+    pub struct ArrowFeatureWriter {
+        writer: FileWriter<File>,
+        schema: Arc<Schema>,
+        rows: Vec<FeatureRow>,
+    }
+
+    impl ArrowFeatureWriter {
+        pub fn create(path: &Path, depths: &[usize]) -> anyhow::Result<Self> {
+            let mut fields = vec![
+                Field::new("ts_recv", DataType::UInt64, false),
+                Field::new("instrument_id", DataType::UInt32, false),
+                Field::new("duration_ns", DataType::UInt64, false),
+                Field::new("mid", DataType::Float64, true),
+            ];
+            fields.extend(
+                depths
+                    .iter()
+                    .map(|depth| Field::new(format!("ofi_{depth}"), DataType::Float64, false)),
+            );
+            fields.push(Field::new(COUNT_COLUMNS[0], DataType::Int64, false));
+            fields.extend(
+                COUNT_COLUMNS[1..7]
+                    .iter()
+                    .map(|name| Field::new(*name, DataType::UInt64, false)),
+            );
+            fields.extend(
+                COUNT_COLUMNS[7..]
+                    .iter()
+                    .map(|name| Field::new(*name, DataType::Float64, true)),
+            );
+            let schema = Arc::new(Schema::new(fields));
+            let writer = FileWriter::try_new(File::create(path)?, &schema)?;
+            Ok(Self {
+                writer,
+                schema,
+                rows: Vec::with_capacity(BATCH_ROWS),
+            })
+        }
+
+        pub fn write(&mut self, row: &FeatureRow) -> anyhow::Result<()> {
+            self.rows.push(row.clone());
+            if self.rows.len() >= BATCH_ROWS {
+                self.write_batch()?;
+            }
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> anyhow::Result<()> {
+            self.write_batch()?;
+            self.writer.finish()?;
+            Ok(())
+        }
+
+        fn write_batch(&mut self) -> anyhow::Result<()> {
+            if self.rows.is_empty() {
+                return Ok(());
+            }
+            let rows = std::mem::take(&mut self.rows);
+            let u64_column = |value: fn(&FeatureRow) -> u64| -> ArrayRef {
+                let mut builder = UInt64Builder::with_capacity(rows.len());
+                rows.iter().for_each(|row| builder.append_value(value(row)));
+                Arc::new(builder.finish())
+            };
+            let f64_column = |value: &dyn Fn(&FeatureRow) -> Option<f64>| -> ArrayRef {
+                let mut builder = Float64Builder::with_capacity(rows.len());
+                rows.iter()
+                    .for_each(|row| builder.append_option(value(row)));
+                Arc::new(builder.finish())
+            };
+
+            let mut instrument_ids = UInt32Builder::with_capacity(rows.len());
+            let mut signed_volume = Int64Builder::with_capacity(rows.len());
+            for row in &rows {
+                instrument_ids.append_value(row.instrument_id);
+                signed_volume.append_value(row.signed_volume);
+            }
+            let mut columns = vec![
+                u64_column(|row| row.ts_recv),
+                Arc::new(instrument_ids.finish()),
+                u64_column(|row| row.duration_ns),
+                f64_column(&|row| row.mid),
+            ];
+            let depth_count = rows.first().map_or(0, |row| row.ofi.len());
+            for depth_idx in 0..depth_count {
+                columns.push(f64_column(&|row| Some(row.ofi[depth_idx])));
+            }
+            columns.push(Arc::new(signed_volume.finish()));
+            columns.extend([
+                u64_column(|row| row.trade_volume),
+                u64_column(|row| row.trades),
+                u64_column(|row| row.bid_adds),
+                u64_column(|row| row.ask_adds),
+                u64_column(|row| row.bid_cancels),
+                u64_column(|row| row.ask_cancels),
+                f64_column(&|row| row.intensity(row.bid_adds)),
+                f64_column(&|row| row.intensity(row.ask_adds)),
+                f64_column(&|row| row.intensity(row.bid_cancels)),
+                f64_column(&|row| row.intensity(row.ask_cancels)),
+                f64_column(&|row| row.cancel_to_trade()),
+            ]);
+            let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+            self.writer.write(&batch)?;
+            Ok(())
+        }
+    }
+}
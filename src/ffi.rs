@@ -0,0 +1,342 @@
+//! C API around [`Market`]; the header is `include/mbo_orderbook.h`, generated by cbindgen.
+//!
+//! Every function takes the market returned by [`mbo_market_new`] and returns an
+//! [`MboStatus`], writing results into caller-provided buffers. Missing levels are reported
+//! with `price = MBO_UNDEF_PRICE` and zero size and count.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+use databento::dbn::{MboMsg, Publisher, UNDEF_PRICE};
+
+use crate::{
+    common::mbo_records,
+    orderbook::{Book, Market, PriceLevel},
+};
+
+/// Price of a missing level.
+pub const MBO_UNDEF_PRICE: i64 = i64::MAX;
+
+/// Opaque handle to a `Market`.
+pub struct MboMarket(Market);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MboStatus {
+    Ok = 0,
+    /// No such book, level or order; the output was left unchanged or zeroed.
+    NotFound = 1,
+    NullPointer = -1,
+    /// The bytes are neither a DBN stream nor whole DBN records.
+    InvalidRecord = -2,
+    UnknownPublisher = -3,
+    /// A record was inconsistent with the book, e.g. a duplicate add. The market shouldn't be
+    /// used any further.
+    BookError = -4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MboPriceLevel {
+    pub price: i64,
+    pub size: u32,
+    pub count: u32,
+}
+
+/// Same layout as DBN's `BidAskPair`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MboBidAskPair {
+    pub bid_px: i64,
+    pub ask_px: i64,
+    pub bid_sz: u32,
+    pub ask_sz: u32,
+    pub bid_ct: u32,
+    pub ask_ct: u32,
+}
+
+/// A resting order as last added or modified.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MboOrder {
+    pub ts_event: u64,
+    pub ts_recv: u64,
+    pub order_id: u64,
+    pub price: i64,
+    pub size: u32,
+    pub instrument_id: u32,
+    pub sequence: u32,
+    pub publisher_id: u16,
+    /// `'B'` or `'A'`.
+    pub side: u8,
+    pub flags: u8,
+}
+
+/// Create an empty market. Free it with `mbo_market_free`.
+#[no_mangle]
+pub extern "C" fn mbo_market_new() -> *mut MboMarket {
+    Box::into_raw(Box::new(MboMarket(Market::new())))
+}
+
+/// Free a market created by `mbo_market_new`. `market` may be null.
+///
+/// # Safety
+/// `market` must come from `mbo_market_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_free(market: *mut MboMarket) {
+    if !market.is_null() {
+        drop(Box::from_raw(market));
+    }
+}
+
+/// Apply the MBO records in `data`: either a DBN stream with its metadata or concatenated raw
+/// DBN records. Other record types are skipped. The number of records applied is written to
+/// `applied` unless it's null.
+///
+/// # Safety
+/// `market` must be a live market and `data` point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_apply(
+    market: *mut MboMarket,
+    data: *const u8,
+    len: usize,
+    applied: *mut usize,
+) -> MboStatus {
+    let Some(market) = market.as_mut() else {
+        return MboStatus::NullPointer;
+    };
+    if data.is_null() && len > 0 {
+        return MboStatus::NullPointer;
+    }
+    let data = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    let Ok(records) = mbo_records(data) else {
+        return MboStatus::InvalidRecord;
+    };
+    if records
+        .iter()
+        .any(|mbo| Publisher::try_from(mbo.hd.publisher_id).is_err())
+    {
+        return MboStatus::UnknownPublisher;
+    }
+    let count = records.len();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        for mbo in records {
+            market.0.apply(mbo);
+        }
+    }));
+    if result.is_err() {
+        return MboStatus::BookError;
+    }
+    if let Some(applied) = applied.as_mut() {
+        *applied = count;
+    }
+    MboStatus::Ok
+}
+
+/// Best bid and offer of one book.
+///
+/// # Safety
+/// `market` must be a live market; `bid` and `ask` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_bbo(
+    market: *const MboMarket,
+    instrument_id: u32,
+    publisher_id: u16,
+    bid: *mut MboPriceLevel,
+    ask: *mut MboPriceLevel,
+) -> MboStatus {
+    let (Some(market), Some(bid), Some(ask)) = (market.as_ref(), bid.as_mut(), ask.as_mut()) else {
+        return MboStatus::NullPointer;
+    };
+    let book = match book(market, instrument_id, publisher_id) {
+        Ok(book) => book,
+        Err(status) => return status,
+    };
+    let (best_bid, best_ask) = book.map(Book::bbo).unwrap_or_default();
+    *bid = best_bid.into();
+    *ask = best_ask.into();
+    if book.is_some() {
+        MboStatus::Ok
+    } else {
+        MboStatus::NotFound
+    }
+}
+
+/// Best bid and offer across every publisher's book for the instrument.
+///
+/// # Safety
+/// `market` must be a live market; `bid` and `ask` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_aggregated_bbo(
+    market: *const MboMarket,
+    instrument_id: u32,
+    bid: *mut MboPriceLevel,
+    ask: *mut MboPriceLevel,
+) -> MboStatus {
+    let (Some(market), Some(bid), Some(ask)) = (market.as_ref(), bid.as_mut(), ask.as_mut()) else {
+        return MboStatus::NullPointer;
+    };
+    let (best_bid, best_ask) = market.0.aggregated_bbo(instrument_id);
+    *bid = best_bid.into();
+    *ask = best_ask.into();
+    if market.0.books_by_pub(instrument_id).is_some() {
+        MboStatus::Ok
+    } else {
+        MboStatus::NotFound
+    }
+}
+
+/// Top `level_count` levels of one book, best first, into `levels`. The number of levels
+/// with a bid or an ask is written to `filled` unless it's null.
+///
+/// # Safety
+/// `market` must be a live market and `levels` point to `level_count` writable pairs.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_depth(
+    market: *const MboMarket,
+    instrument_id: u32,
+    publisher_id: u16,
+    levels: *mut MboBidAskPair,
+    level_count: usize,
+    filled: *mut usize,
+) -> MboStatus {
+    let Some(market) = market.as_ref() else {
+        return MboStatus::NullPointer;
+    };
+    if levels.is_null() && level_count > 0 {
+        return MboStatus::NullPointer;
+    }
+    let book = match book(market, instrument_id, publisher_id) {
+        Ok(book) => book,
+        Err(status) => return status,
+    };
+    let snapshot = match book {
+        Some(book) => book.snapshot(level_count),
+        None => vec![Default::default(); level_count],
+    };
+    let mut count = 0;
+    for (idx, pair) in snapshot.iter().enumerate() {
+        if pair.bid_px != UNDEF_PRICE || pair.ask_px != UNDEF_PRICE {
+            count = idx + 1;
+        }
+        levels.add(idx).write(MboBidAskPair {
+            bid_px: pair.bid_px,
+            ask_px: pair.ask_px,
+            bid_sz: pair.bid_sz,
+            ask_sz: pair.ask_sz,
+            bid_ct: pair.bid_ct,
+            ask_ct: pair.ask_ct,
+        });
+    }
+    if let Some(filled) = filled.as_mut() {
+        *filled = count;
+    }
+    if book.is_some() {
+        MboStatus::Ok
+    } else {
+        MboStatus::NotFound
+    }
+}
+
+/// Look up a resting order.
+///
+/// # Safety
+/// `market` must be a live market and `order` writable.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_order(
+    market: *const MboMarket,
+    instrument_id: u32,
+    publisher_id: u16,
+    order_id: u64,
+    order: *mut MboOrder,
+) -> MboStatus {
+    let (Some(market), Some(order)) = (market.as_ref(), order.as_mut()) else {
+        return MboStatus::NullPointer;
+    };
+    match book(market, instrument_id, publisher_id) {
+        Ok(book) => match book.and_then(|book| book.order(order_id)) {
+            Some(mbo) => {
+                *order = mbo.into();
+                MboStatus::Ok
+            }
+            None => MboStatus::NotFound,
+        },
+        Err(status) => status,
+    }
+}
+
+/// Size resting ahead of an order at its price level.
+///
+/// # Safety
+/// `market` must be a live market and `queue_pos` writable.
+#[no_mangle]
+pub unsafe extern "C" fn mbo_market_queue_pos(
+    market: *const MboMarket,
+    instrument_id: u32,
+    publisher_id: u16,
+    order_id: u64,
+    queue_pos: *mut u32,
+) -> MboStatus {
+    let (Some(market), Some(queue_pos)) = (market.as_ref(), queue_pos.as_mut()) else {
+        return MboStatus::NullPointer;
+    };
+    match book(market, instrument_id, publisher_id) {
+        Ok(book) => match book.and_then(|book| book.queue_pos(order_id)) {
+            Some(pos) => {
+                *queue_pos = pos;
+                MboStatus::Ok
+            }
+            None => MboStatus::NotFound,
+        },
+        Err(status) => status,
+    }
+}
+
+fn book(
+    market: &MboMarket,
+    instrument_id: u32,
+    publisher_id: u16,
+) -> Result<Option<&Book>, MboStatus> {
+    let publisher = Publisher::try_from(publisher_id).map_err(|_| MboStatus::UnknownPublisher)?;
+    Ok(market.0.book(instrument_id, publisher))
+}
+
+impl From<Option<PriceLevel>> for MboPriceLevel {
+    fn from(level: Option<PriceLevel>) -> Self {
+        match level {
+            Some(level) => Self {
+                price: level.price,
+                size: level.size,
+                count: level.count,
+            },
+            None => Self {
+                price: UNDEF_PRICE,
+                size: 0,
+                count: 0,
+            },
+        }
+    }
+}
+
+impl From<&MboMsg> for MboOrder {
+    fn from(mbo: &MboMsg) -> Self {
+        Self {
+            ts_event: mbo.hd.ts_event,
+            ts_recv: mbo.ts_recv,
+            order_id: mbo.order_id,
+            price: mbo.price,
+            size: mbo.size,
+            instrument_id: mbo.hd.instrument_id,
+            sequence: mbo.sequence,
+            publisher_id: mbo.hd.publisher_id,
+            side: mbo.side as u8,
+            flags: mbo.flags.raw(),
+        }
+    }
+}
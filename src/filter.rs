@@ -0,0 +1,143 @@
+use std::{collections::HashSet, num::NonZeroU64};
+
+use databento::dbn::{Action, MboMsg, Metadata, SType};
+
+use crate::common::instrument_symbols;
+
+/// Selects MBO records by instrument, publisher, action and `ts_event` window. Empty sets
+/// match everything.
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    pub instrument_ids: HashSet<u32>,
+    pub publisher_ids: HashSet<u16>,
+    pub actions: HashSet<Action>,
+    /// Inclusive start of the `ts_event` window in UNIX nanoseconds.
+    pub start: Option<u64>,
+    /// Exclusive end of the `ts_event` window in UNIX nanoseconds.
+    pub end: Option<u64>,
+}
+
+impl RecordFilter {
+    /// Add the instruments of raw `symbols`, resolved through the `metadata` symbology.
+    pub fn with_symbols(mut self, metadata: &Metadata, symbols: &[String]) -> anyhow::Result<Self> {
+        let ids = instrument_symbols(metadata);
+        for symbol in symbols {
+            let matched: Vec<u32> = ids
+                .iter()
+                .filter(|(_, raw_symbol)| *raw_symbol == symbol)
+                .map(|(id, _)| *id)
+                .collect();
+            if matched.is_empty() {
+                anyhow::bail!("symbol {symbol:?} not found in the file's symbology");
+            }
+            self.instrument_ids.extend(matched);
+        }
+        Ok(self)
+    }
+
+    /// The narrowest single filter passing every record that any of `filters` passes.
+    pub fn union(filters: &[RecordFilter]) -> Self {
+        fn union_set<T: Copy + Eq + std::hash::Hash>(sets: Vec<&HashSet<T>>) -> HashSet<T> {
+            if sets.iter().any(|set| set.is_empty()) {
+                HashSet::new()
+            } else {
+                sets.into_iter().flatten().copied().collect()
+            }
+        }
+        let bound = |bound: fn(&Self) -> Option<u64>, pick: fn(u64, u64) -> u64| {
+            filters
+                .iter()
+                .map(bound)
+                .reduce(|a, b| a.zip(b).map(|(a, b)| pick(a, b)))
+                .flatten()
+        };
+        Self {
+            instrument_ids: union_set(filters.iter().map(|f| &f.instrument_ids).collect()),
+            publisher_ids: union_set(filters.iter().map(|f| &f.publisher_ids).collect()),
+            actions: union_set(filters.iter().map(|f| &f.actions).collect()),
+            start: bound(|f| f.start, u64::min),
+            end: bound(|f| f.end, u64::max),
+        }
+    }
+
+    /// Whether every record passes.
+    pub fn is_empty(&self) -> bool {
+        self.instrument_ids.is_empty()
+            && self.publisher_ids.is_empty()
+            && self.actions.is_empty()
+            && self.start.is_none()
+            && self.end.is_none()
+    }
+
+    pub fn matches(&self, mbo: &MboMsg) -> bool {
+        self.matches_book(mbo.hd.instrument_id, mbo.hd.publisher_id)
+            && (self.actions.is_empty()
+                || mbo
+                    .action()
+                    .is_ok_and(|action| self.actions.contains(&action)))
+            && self.start.is_none_or(|start| mbo.hd.ts_event >= start)
+            && self.end.is_none_or(|end| mbo.hd.ts_event < end)
+    }
+
+    /// Whether records of the book of `instrument_id` and `publisher_id` can pass.
+    pub fn matches_book(&self, instrument_id: u32, publisher_id: u16) -> bool {
+        (self.instrument_ids.is_empty() || self.instrument_ids.contains(&instrument_id))
+            && (self.publisher_ids.is_empty() || self.publisher_ids.contains(&publisher_id))
+    }
+
+    /// `metadata` narrowed to the filtered instruments and time range.
+    pub fn metadata(&self, metadata: &Metadata) -> Metadata {
+        let mut metadata = metadata.clone();
+        if let Some(start) = self.start {
+            metadata.start = metadata.start.max(start);
+        }
+        if let Some(end) = self.end.and_then(NonZeroU64::new) {
+            metadata.end = Some(metadata.end.map_or(end, |md_end| md_end.min(end)));
+        }
+        if self.instrument_ids.is_empty() {
+            return metadata;
+        }
+        let by_id = metadata.stype_in == Some(SType::InstrumentId);
+        for mapping in &mut metadata.mappings {
+            let raw_symbol = mapping.raw_symbol.clone();
+            // See `instrument_symbols` for which side of the mapping holds the ID
+            mapping.intervals.retain(|interval| {
+                let id = if by_id { &raw_symbol } else { &interval.symbol };
+                id.parse().is_ok_and(|id| self.instrument_ids.contains(&id))
+            });
+        }
+        metadata
+            .mappings
+            .retain(|mapping| !mapping.intervals.is_empty());
+        let kept: HashSet<&str> = metadata
+            .mappings
+            .iter()
+            .map(|mapping| mapping.raw_symbol.as_str())
+            .collect();
+        metadata
+            .symbols
+            .retain(|symbol| kept.contains(symbol.as_str()));
+        metadata
+            .partial
+            .retain(|symbol| kept.contains(symbol.as_str()));
+        metadata
+    }
+}
+
+/// Parse an action from its DBN code (`A`, `C`, `M`, `R`, `T`, `F`, `N`) or name.
+pub fn parse_action(s: &str) -> Result<Action, String> {
+    let action = match s.to_ascii_lowercase().as_str() {
+        "add" => Action::Add,
+        "cancel" => Action::Cancel,
+        "modify" => Action::Modify,
+        "clear" => Action::Clear,
+        "trade" => Action::Trade,
+        "fill" => Action::Fill,
+        "none" => Action::None,
+        _ => match s.as_bytes() {
+            [code] => Action::try_from(*code).map_err(|_| format!("unknown action {s:?}"))?,
+            _ => return Err(format!("unknown action {s:?}")),
+        },
+    };
+    Ok(action)
+}
@@ -0,0 +1,171 @@
+//! Server side of the Databento Live gateway protocol, so `databento::LiveClient` can connect
+//! to a local replay.
+//!
+//! The gateway greets the client and sends a CRAM challenge; the client answers with
+//! `auth=<sha256(challenge|key)>-<bucket id>|dataset=...`, then sends subscription requests
+//! until `start_session`, after which the gateway sends DBN `Metadata` and records.
+
+use std::collections::HashMap;
+
+use databento::dbn::{Metadata, SType, Schema};
+use rand::{distr::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    filter::RecordFilter,
+    subscription::{read_line, write_line},
+};
+
+const LSG_VERSION: &str = "0.0.0";
+const ALL_SYMBOLS: &str = "ALL_SYMBOLS";
+/// Length of the trailing part of an API key sent in the clear.
+const BUCKET_ID_LEN: usize = 5;
+
+/// A client authenticated and subscribed through the Live protocol.
+#[derive(Debug, Clone)]
+pub struct LiveSession {
+    pub dataset: String,
+    /// Whether to append a gateway send timestamp to every record.
+    pub ts_out: bool,
+    pub subscriptions: Vec<LiveSubscription>,
+}
+
+/// One `schema=...|stype_in=...|symbols=...` request. Requests for more than 500 symbols
+/// arrive in several chunks, each its own `LiveSubscription`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveSubscription {
+    pub schema: String,
+    pub stype_in: String,
+    /// Empty for `ALL_SYMBOLS`.
+    pub symbols: Vec<String>,
+    /// Intraday replay start in UNIX nanoseconds.
+    pub start: Option<u64>,
+    /// Requests a book snapshot first; rejected by [`LiveSubscription::filter`].
+    pub snapshot: bool,
+    pub id: Option<u32>,
+}
+
+/// Greet and authenticate a client against `key`, then read its subscriptions until it
+/// starts the session. Clients failing authentication are told why before the error returns.
+pub async fn accept<S>(
+    stream: &mut S,
+    key: &str,
+    dataset: &str,
+    session_id: u64,
+) -> anyhow::Result<LiveSession>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    write_line(stream, &format!("lsg_version={LSG_VERSION}")).await?;
+    let challenge: String = rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    write_line(stream, &format!("cram={challenge}")).await?;
+
+    let request = read_line(stream).await?;
+    let fields = parse_fields(&request);
+    let error = if fields.get("auth").copied() != Some(cram_response(&challenge, key).as_str()) {
+        Some("Authentication failed.".to_owned())
+    } else if fields.get("dataset").copied() != Some(dataset) {
+        Some(format!("Unknown dataset, only {dataset} is available."))
+    } else if fields
+        .get("encoding")
+        .is_some_and(|encoding| *encoding != "dbn")
+    {
+        Some("Only DBN encoding is supported.".to_owned())
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        write_line(stream, &format!("success=0|error={error}")).await?;
+        anyhow::bail!("live authentication failed: {error}");
+    }
+    let ts_out = fields.get("ts_out").is_some_and(|ts_out| *ts_out == "1");
+    write_line(stream, &format!("success=1|error=|session_id={session_id}")).await?;
+
+    let mut subscriptions = Vec::new();
+    loop {
+        let request = read_line(stream).await?;
+        if request == "start_session" {
+            break;
+        }
+        subscriptions.push(LiveSubscription::parse(&request)?);
+    }
+    Ok(LiveSession {
+        dataset: dataset.to_owned(),
+        ts_out,
+        subscriptions,
+    })
+}
+
+/// The `auth` value a client holding `key` sends for `challenge`.
+pub fn cram_response(challenge: &str, key: &str) -> String {
+    let hash = Sha256::digest(format!("{challenge}|{key}").as_bytes());
+    let hex: String = hash.iter().map(|byte| format!("{byte:02x}")).collect();
+    let bucket_id = &key[key.len().saturating_sub(BUCKET_ID_LEN)..];
+    format!("{hex}-{bucket_id}")
+}
+
+impl LiveSubscription {
+    pub fn parse(request: &str) -> anyhow::Result<Self> {
+        let fields = parse_fields(request);
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("subscription request missing {key}: {request:?}"))
+        };
+        let symbols = field("symbols")?;
+        Ok(Self {
+            schema: field("schema")?.to_owned(),
+            stype_in: field("stype_in")?.to_owned(),
+            symbols: if symbols == ALL_SYMBOLS {
+                Vec::new()
+            } else {
+                symbols.split(',').map(str::to_owned).collect()
+            },
+            start: fields.get("start").map(|start| start.parse()).transpose()?,
+            snapshot: fields
+                .get("snapshot")
+                .is_some_and(|snapshot| *snapshot == "1"),
+            id: fields.get("id").map(|id| id.parse()).transpose()?,
+        })
+    }
+
+    /// The records this subscription selects from a file with `metadata`.
+    pub fn filter(&self, metadata: &Metadata) -> anyhow::Result<RecordFilter> {
+        if self.schema.parse::<Schema>().ok() != Some(Schema::Mbo) {
+            anyhow::bail!("unsupported schema {}, only mbo is available", self.schema);
+        }
+        if self.snapshot {
+            anyhow::bail!("snapshot subscriptions are not supported");
+        }
+        let filter = RecordFilter {
+            start: self.start,
+            ..RecordFilter::default()
+        };
+        match self.stype_in.parse::<SType>() {
+            Ok(SType::RawSymbol) => filter.with_symbols(metadata, &self.symbols),
+            Ok(SType::InstrumentId) => Ok(RecordFilter {
+                instrument_ids: self
+                    .symbols
+                    .iter()
+                    .map(|id| id.parse())
+                    .collect::<Result<_, _>>()?,
+                ..filter
+            }),
+            _ => anyhow::bail!("unsupported stype_in {}", self.stype_in),
+        }
+    }
+}
+
+/// Split `key=value|key=value` control messages.
+fn parse_fields(message: &str) -> HashMap<&str, &str> {
+    message
+        .split('|')
+        .filter_map(|field| field.split_once('='))
+        .collect()
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use databento::dbn::Publisher;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Delay in nanoseconds applied to one direction of the simulated link.
+#[derive(Debug, Clone)]
+pub enum LatencyModel {
+    Constant(u64),
+    Empirical(EmpiricalLatency),
+    /// Per-venue models, falling back to `default` for unlisted publishers.
+    PerVenue {
+        venues: HashMap<Publisher, LatencyModel>,
+        default: Box<LatencyModel>,
+    },
+}
+
+/// Latencies drawn uniformly from a set of observed samples.
+#[derive(Debug, Clone)]
+pub struct EmpiricalLatency {
+    samples: Vec<u64>,
+    rng: Box<StdRng>,
+}
+
+/// Order-entry and market-data latency used by the backtest.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyConfig {
+    /// Strategy decision until the simulated exchange sees the order or cancel.
+    pub order_entry: LatencyModel,
+    /// Exchange event (`ts_recv`) until the strategy sees it.
+    pub market_data: LatencyModel,
+}
+
+impl Default for LatencyModel {
+    fn default() -> Self {
+        Self::Constant(0)
+    }
+}
+
+impl LatencyModel {
+    pub fn sample(&mut self, publisher: Publisher) -> u64 {
+        match self {
+            Self::Constant(ns) => *ns,
+            Self::Empirical(empirical) => empirical.sample(),
+            Self::PerVenue { venues, default } => venues
+                .get_mut(&publisher)
+                .unwrap_or(default)
+                .sample(publisher),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Self::Constant(ns) => *ns == 0,
+            Self::Empirical(empirical) => empirical.samples.iter().all(|ns| *ns == 0),
+            Self::PerVenue { venues, default } => {
+                default.is_zero() && venues.values().all(LatencyModel::is_zero)
+            }
+        }
+    }
+}
+
+impl EmpiricalLatency {
+    pub fn new(samples: Vec<u64>, seed: u64) -> Self {
+        assert!(!samples.is_empty(), "empirical latency needs samples");
+        Self {
+            samples,
+            rng: Box::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Load samples in nanoseconds, one per line; blank lines and `#` comments are skipped.
+    pub fn from_file(path: impl AsRef<Path>, seed: u64) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let samples = fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if samples.is_empty() {
+            anyhow::bail!("no latency samples in {}", path.display());
+        }
+        Ok(Self::new(samples, seed))
+    }
+
+    pub fn sample(&mut self) -> u64 {
+        self.samples[self.rng.random_range(0..self.samples.len())]
+    }
+}
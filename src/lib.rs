@@ -1,2 +1,24 @@
+pub mod accounting;
+pub mod backtest;
 pub mod common;
+pub mod compression;
+pub mod dump;
+pub mod features;
+pub mod ffi;
+pub mod filter;
+pub mod gateway;
+pub mod latency;
+pub mod lint;
+pub mod merge;
+pub mod metrics;
+pub mod monitor;
+pub mod order_stats;
 pub mod orderbook;
+pub mod outbox;
+pub mod pacing;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod rebase;
+pub mod sampler;
+pub mod subscription;
+pub mod verify;
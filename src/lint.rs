@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+
+use databento::dbn::{Action, MboMsg, Publisher, Record, Side, UNDEF_PRICE};
+use serde::Serialize;
+
+use crate::{
+    monitor::{BookEventKind, BookMonitor},
+    orderbook::{Crossing, Market},
+};
+
+/// A problem found in an MBO record, usually one `Book` would silently tolerate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anomaly {
+    /// Cancel for an order that isn't resting; ignored by the book.
+    UnknownCancel,
+    /// Cancel whose side or price differs from the resting order; ignored by the book.
+    MismatchedCancel,
+    /// Cancel larger than the resting size; clamped by the book.
+    CancelExceedsSize,
+    /// Modify for an order that isn't resting; treated as an add by the book.
+    UnknownModify,
+    /// Modify that moves an order to the other side; not applied.
+    SideChange,
+    /// Add for an order ID that is already resting; not applied.
+    DuplicateAdd,
+    /// Add or modify leaving an order with zero size.
+    ZeroSize,
+    /// Add or modify with an undefined price outside top-of-book records; not applied.
+    UndefPrice,
+    /// Add, cancel, modify or fill without a side; not applied.
+    SideNone,
+    /// Unrecognized action or publisher; not applied.
+    InvalidRecord,
+    /// Sequence number jumped forward by more than one; only reported with
+    /// [`Linter::with_sequence_gaps`].
+    SequenceGap,
+    /// Sequence number went backwards.
+    SequenceRegression,
+    /// `ts_recv` went backwards.
+    TimestampRegression,
+    LockedBook,
+    CrossedBook,
+}
+
+/// Replays records into a [`Market`], skipping those that would corrupt it, and counts anomalies.
+#[derive(Debug, Default)]
+pub struct Linter {
+    market: Market,
+    monitor: BookMonitor,
+    // Last sequence per publisher and channel
+    sequences: HashMap<(u16, u8), u32>,
+    sequence_gaps: bool,
+    ts_recv: HashMap<u16, u64>,
+    counts: HashMap<(u32, u16), BTreeMap<Anomaly, u64>>,
+    records: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintBookReport {
+    pub instrument_id: u32,
+    pub publisher_id: u16,
+    pub publisher: Option<Publisher>,
+    pub anomalies: BTreeMap<Anomaly, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintReport {
+    pub records: u64,
+    pub anomalies: BTreeMap<Anomaly, u64>,
+    pub books: Vec<LintBookReport>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also report gaps in each channel's sequence numbers. Only meaningful when the data holds
+    /// every instrument of the channel: a file filtered to a few instruments skips the sequence
+    /// numbers of the others.
+    pub fn with_sequence_gaps() -> Self {
+        Self {
+            sequence_gaps: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn market(&self) -> &Market {
+        &self.market
+    }
+
+    /// Check `mbo` and apply it to the market unless doing so would corrupt a book.
+    pub fn lint(&mut self, mbo: &MboMsg) -> Vec<Anomaly> {
+        self.records += 1;
+        let mut anomalies = self.check_stream(mbo);
+        let (apply, record_anomalies) = Self::check_record(&self.market, mbo);
+        anomalies.extend(record_anomalies);
+        if apply {
+            self.market.apply(mbo.clone());
+            for event in self.monitor.check(&self.market, mbo) {
+                match event.kind {
+                    BookEventKind::Crossing {
+                        crossing: Crossing::Locked,
+                        ..
+                    } => anomalies.push(Anomaly::LockedBook),
+                    BookEventKind::Crossing {
+                        crossing: Crossing::Crossed,
+                        ..
+                    } => anomalies.push(Anomaly::CrossedBook),
+                    BookEventKind::Uncrossed | BookEventKind::Invalid(_) => {}
+                }
+            }
+        }
+        let counts = self
+            .counts
+            .entry((mbo.hd.instrument_id, mbo.hd.publisher_id))
+            .or_default();
+        for anomaly in &anomalies {
+            *counts.entry(*anomaly).or_default() += 1;
+        }
+        anomalies
+    }
+
+    pub fn report(&self) -> LintReport {
+        let mut anomalies = BTreeMap::new();
+        let mut books: Vec<LintBookReport> = self
+            .counts
+            .iter()
+            .filter(|(_, counts)| !counts.is_empty())
+            .map(|((instrument_id, publisher_id), counts)| {
+                for (anomaly, count) in counts {
+                    *anomalies.entry(*anomaly).or_default() += count;
+                }
+                LintBookReport {
+                    instrument_id: *instrument_id,
+                    publisher_id: *publisher_id,
+                    publisher: Publisher::try_from(*publisher_id).ok(),
+                    anomalies: counts.clone(),
+                }
+            })
+            .collect();
+        books.sort_by_key(|book| (book.instrument_id, book.publisher_id));
+        LintReport {
+            records: self.records,
+            anomalies,
+            books,
+        }
+    }
+
+    /// Sequence and timestamp checks across all records of a publisher.
+    fn check_stream(&mut self, mbo: &MboMsg) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        let publisher_id = mbo.hd.publisher_id;
+        // Zero means the venue doesn't provide sequence numbers
+        if mbo.sequence != 0 {
+            if let Some(last) = self
+                .sequences
+                .insert((publisher_id, mbo.channel_id), mbo.sequence)
+            {
+                if mbo.sequence < last {
+                    anomalies.push(Anomaly::SequenceRegression);
+                } else if self.sequence_gaps && mbo.sequence > last.saturating_add(1) {
+                    anomalies.push(Anomaly::SequenceGap);
+                }
+            }
+        }
+        if let Some(last) = self.ts_recv.insert(publisher_id, mbo.ts_recv) {
+            if mbo.ts_recv < last {
+                anomalies.push(Anomaly::TimestampRegression);
+            }
+        }
+        anomalies
+    }
+
+    /// Order-level checks of `mbo` against `market`; returns whether the record is safe to
+    /// apply, i.e. won't make [`Market::apply`] panic or corrupt a book.
+    pub fn check_record(market: &Market, mbo: &MboMsg) -> (bool, Vec<Anomaly>) {
+        let (Ok(action), Ok(side), Ok(publisher)) = (mbo.action(), mbo.side(), mbo.publisher())
+        else {
+            return (false, vec![Anomaly::InvalidRecord]);
+        };
+        let needs_side = matches!(
+            action,
+            Action::Add | Action::Cancel | Action::Modify | Action::Fill
+        );
+        if needs_side && side == Side::None {
+            return (false, vec![Anomaly::SideNone]);
+        }
+        if mbo.flags.is_tob() {
+            return (true, Vec::new());
+        }
+        if matches!(action, Action::Add | Action::Modify) && mbo.price == UNDEF_PRICE {
+            return (false, vec![Anomaly::UndefPrice]);
+        }
+        let resting = market
+            .book(mbo.hd.instrument_id, publisher)
+            .and_then(|book| book.order(mbo.order_id));
+        let mut anomalies = Vec::new();
+        match action {
+            Action::Add => {
+                if resting.is_some() {
+                    return (false, vec![Anomaly::DuplicateAdd]);
+                }
+                if mbo.size == 0 {
+                    anomalies.push(Anomaly::ZeroSize);
+                }
+            }
+            Action::Modify => {
+                match resting {
+                    None => anomalies.push(Anomaly::UnknownModify),
+                    Some(order) if order.side != mbo.side => {
+                        return (false, vec![Anomaly::SideChange])
+                    }
+                    Some(_) => {}
+                }
+                if mbo.size == 0 {
+                    anomalies.push(Anomaly::ZeroSize);
+                }
+            }
+            Action::Cancel => match resting {
+                None => anomalies.push(Anomaly::UnknownCancel),
+                Some(order) if order.price != mbo.price || order.side != mbo.side => {
+                    anomalies.push(Anomaly::MismatchedCancel)
+                }
+                Some(order) if order.size < mbo.size => anomalies.push(Anomaly::CancelExceedsSize),
+                Some(_) => {}
+            },
+            Action::Trade | Action::Fill | Action::Clear | Action::None => {}
+        }
+        (true, anomalies)
+    }
+}
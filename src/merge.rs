@@ -0,0 +1,108 @@
+//! Several DBN files, e.g. one per symbol and day, replayed as one stream in the order the
+//! records were received.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    path::{Path, PathBuf},
+};
+
+use databento::dbn::{
+    decode::{AsyncDbnDecoder, DbnMetadata},
+    MboMsg, Metadata,
+};
+
+use crate::compression::{file_decoder, DbnReader};
+
+/// Decodes the MBO records of several DBN files as one stream ordered by `ts_recv`, ties broken
+/// by `sequence` and then by file order. Each file must already be in `ts_recv` order; a
+/// single file is passed through as is.
+pub struct MergedDecoder {
+    /// The files' metadata merged, symbology included.
+    metadata: Metadata,
+    decoders: Vec<AsyncDbnDecoder<DbnReader>>,
+    /// Each file's next record, `None` once it's exhausted.
+    heads: Vec<Option<MboMsg>>,
+    /// Files with a next record, keyed by its `(ts_recv, sequence)`.
+    queue: BinaryHeap<Reverse<(u64, u32, usize)>>,
+    /// The record last returned.
+    current: Option<MboMsg>,
+}
+
+impl MergedDecoder {
+    /// Open `paths`, zstd-compressed or not, and merge their metadata.
+    pub async fn open(paths: &[impl AsRef<Path>]) -> anyhow::Result<Self> {
+        let mut decoders = Vec::with_capacity(paths.len());
+        for path in paths {
+            decoders.push(file_decoder(path).await?);
+        }
+        let metadata = {
+            let mut metadata = decoders.iter().map(|decoder| decoder.metadata().clone());
+            let Some(first) = metadata.next() else {
+                anyhow::bail!("no DBN files to decode");
+            };
+            first.merge(metadata)?
+        };
+
+        let mut merged = Self {
+            metadata,
+            heads: vec![None; decoders.len()],
+            decoders,
+            queue: BinaryHeap::new(),
+            current: None,
+        };
+        for idx in 0..merged.decoders.len() {
+            merged.advance(idx).await?;
+        }
+        Ok(merged)
+    }
+
+    /// The next record across all files, or `None` once every file is exhausted.
+    pub async fn decode_record(&mut self) -> databento::dbn::Result<Option<&MboMsg>> {
+        let Some(Reverse((_, _, idx))) = self.queue.pop() else {
+            return Ok(None);
+        };
+        self.current = self.heads[idx].take();
+        self.advance(idx).await?;
+        Ok(self.current.as_ref())
+    }
+
+    /// Read the next record of file `idx` and queue it.
+    async fn advance(&mut self, idx: usize) -> databento::dbn::Result<()> {
+        if let Some(mbo) = self.decoders[idx].decode_record::<MboMsg>().await? {
+            self.queue.push(Reverse((mbo.ts_recv, mbo.sequence, idx)));
+            self.heads[idx] = Some(mbo.clone());
+        }
+        Ok(())
+    }
+}
+
+impl DbnMetadata for MergedDecoder {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+/// Expand glob patterns like `data/*.mbo.dbn.zst` in `inputs`, each into its matches in
+/// alphabetical order. Inputs without wildcards are taken as paths even if they don't exist,
+/// so opening them reports the error.
+pub fn expand_paths(inputs: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            paths.push(input.clone());
+            continue;
+        }
+        let matched = glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?;
+        if matched.is_empty() {
+            anyhow::bail!("no files match {pattern:?}");
+        }
+        paths.extend(matched);
+    }
+    Ok(paths)
+}
@@ -0,0 +1,164 @@
+use serde::Serialize;
+
+use crate::{
+    common::to_px,
+    orderbook::{Book, Market, PriceLevel},
+};
+
+/// Top levels of both sides of a book, best first, for computing microstructure metrics.
+///
+/// Prices returned by the metrics are in price units (fixed-precision price
+/// divided by `FIXED_PRICE_SCALE`); sizes are in the book's units.
+#[derive(Debug, Clone, Default)]
+pub struct Depth {
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+/// Distance from the mid used by [`Depth::depth_within`].
+#[derive(Debug, Clone, Copy)]
+pub enum Distance {
+    /// `count` ticks of `tick_size` (fixed-precision price).
+    Ticks { count: u32, tick_size: i64 },
+    /// Basis points of the mid.
+    Bps(f64),
+}
+
+/// Commonly used metrics computed together.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    pub spread: Option<f64>,
+    pub spread_bps: Option<f64>,
+    pub mid: Option<f64>,
+    pub microprice: Option<f64>,
+    pub weighted_mid: Option<f64>,
+    pub imbalance: Option<f64>,
+    pub count_imbalance: Option<f64>,
+}
+
+impl Depth {
+    pub fn from_book(book: &Book, level_count: usize) -> Self {
+        Self {
+            bids: book.bid_levels().take(level_count).collect(),
+            asks: book.ask_levels().take(level_count).collect(),
+        }
+    }
+
+    /// Consolidated depth across all publishers of `instrument_id`.
+    pub fn from_market(market: &Market, instrument_id: u32, level_count: usize) -> Self {
+        let (bids, asks) = market.aggregated_levels(instrument_id, level_count);
+        Self { bids, asks }
+    }
+
+    pub fn best_bid(&self) -> Option<&PriceLevel> {
+        self.bids.first()
+    }
+
+    pub fn best_ask(&self) -> Option<&PriceLevel> {
+        self.asks.first()
+    }
+
+    /// Quoted spread.
+    pub fn spread(&self) -> Option<f64> {
+        let (bid, ask) = self.top()?;
+        Some(to_px(ask.price - bid.price))
+    }
+
+    pub fn spread_bps(&self) -> Option<f64> {
+        Some(self.spread()? / self.mid()? * 10_000.0)
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        let (bid, ask) = self.top()?;
+        Some((to_px(bid.price) + to_px(ask.price)) / 2.0)
+    }
+
+    /// Mid weighted towards the side with less size at the top.
+    pub fn microprice(&self) -> Option<f64> {
+        let (bid, ask) = self.top()?;
+        let total = bid.size as f64 + ask.size as f64;
+        if total == 0.0 {
+            return self.mid();
+        }
+        Some((to_px(bid.price) * ask.size as f64 + to_px(ask.price) * bid.size as f64) / total)
+    }
+
+    /// Average of the bid and ask size-weighted prices over the top `levels`.
+    pub fn weighted_mid(&self, levels: usize) -> Option<f64> {
+        let bid = vwap(&self.bids[..levels.min(self.bids.len())])?;
+        let ask = vwap(&self.asks[..levels.min(self.asks.len())])?;
+        Some((bid + ask) / 2.0)
+    }
+
+    /// Size imbalance over the top `levels`, from -1 (all asks) to 1 (all bids).
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        let (bid, ask) = self.side_totals(levels, |level| level.size as u64);
+        imbalance(bid, ask)
+    }
+
+    /// Order count imbalance over the top `levels`, from -1 (all asks) to 1 (all bids).
+    pub fn count_imbalance(&self, levels: usize) -> Option<f64> {
+        let (bid, ask) = self.side_totals(levels, |level| level.count as u64);
+        imbalance(bid, ask)
+    }
+
+    /// Bid and ask size resting within `distance` of the mid.
+    ///
+    /// Only the levels captured in this `Depth` are counted.
+    pub fn depth_within(&self, distance: Distance) -> Option<(u64, u64)> {
+        let (bid, ask) = self.top()?;
+        let mid = (bid.price as f64 + ask.price as f64) / 2.0;
+        let max_distance = match distance {
+            Distance::Ticks { count, tick_size } => count as f64 * tick_size as f64,
+            Distance::Bps(bps) => mid * bps / 10_000.0,
+        };
+        let within = |levels: &[PriceLevel]| {
+            levels
+                .iter()
+                .take_while(|level| (level.price as f64 - mid).abs() <= max_distance)
+                .map(|level| level.size as u64)
+                .sum()
+        };
+        Some((within(&self.bids), within(&self.asks)))
+    }
+
+    pub fn summary(&self, levels: usize) -> Summary {
+        Summary {
+            spread: self.spread(),
+            spread_bps: self.spread_bps(),
+            mid: self.mid(),
+            microprice: self.microprice(),
+            weighted_mid: self.weighted_mid(levels),
+            imbalance: self.imbalance(levels),
+            count_imbalance: self.count_imbalance(levels),
+        }
+    }
+
+    fn top(&self) -> Option<(&PriceLevel, &PriceLevel)> {
+        Some((self.best_bid()?, self.best_ask()?))
+    }
+
+    fn side_totals(&self, levels: usize, value: impl Fn(&PriceLevel) -> u64) -> (u64, u64) {
+        (
+            self.bids.iter().take(levels).map(&value).sum(),
+            self.asks.iter().take(levels).map(&value).sum(),
+        )
+    }
+}
+
+fn vwap(levels: &[PriceLevel]) -> Option<f64> {
+    let size: u64 = levels.iter().map(|level| level.size as u64).sum();
+    if size == 0 {
+        return None;
+    }
+    let notional: f64 = levels
+        .iter()
+        .map(|level| to_px(level.price) * level.size as f64)
+        .sum();
+    Some(notional / size as f64)
+}
+
+fn imbalance(bid: u64, ask: u64) -> Option<f64> {
+    let total = bid + ask;
+    (total > 0).then(|| (bid as f64 - ask as f64) / total as f64)
+}
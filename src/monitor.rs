@@ -0,0 +1,121 @@
+use std::{collections::HashMap, fmt::Display};
+
+use databento::dbn::{pretty, MboMsg, Publisher, Record};
+
+use crate::orderbook::{Crossing, InvariantViolation, Market};
+
+/// Checks books at the end of every event and reports problems as [`BookEvent`]s.
+#[derive(Debug, Default)]
+pub struct BookMonitor {
+    validate: bool,
+    crossings: HashMap<(u32, Publisher), Crossing>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BookEvent {
+    pub ts_recv: u64,
+    pub sequence: u32,
+    pub instrument_id: u32,
+    pub publisher: Publisher,
+    pub kind: BookEventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum BookEventKind {
+    /// The book became locked or crossed, or went from one to the other.
+    Crossing {
+        crossing: Crossing,
+        bid_px: i64,
+        ask_px: i64,
+    },
+    /// The book is no longer locked or crossed.
+    Uncrossed,
+    Invalid(InvariantViolation),
+}
+
+impl BookMonitor {
+    /// `validate` also runs [`Book::validate`](crate::orderbook::Book::validate), which is
+    /// linear in the size of the book.
+    pub fn new(validate: bool) -> Self {
+        Self {
+            validate,
+            ..Self::default()
+        }
+    }
+
+    /// Check the book `mbo` was applied to; call after `Market::apply`.
+    ///
+    /// Only records flagged `F_LAST` are checked, since books can be transiently
+    /// inconsistent within an event.
+    pub fn check(&mut self, market: &Market, mbo: &MboMsg) -> Vec<BookEvent> {
+        let mut events = Vec::new();
+        if !mbo.flags.is_last() {
+            return events;
+        }
+        let Ok(publisher) = mbo.publisher() else {
+            return events;
+        };
+        let instrument_id = mbo.hd.instrument_id;
+        let Some(book) = market.book(instrument_id, publisher) else {
+            return events;
+        };
+        let mut push = |kind| {
+            events.push(BookEvent {
+                ts_recv: mbo.ts_recv,
+                sequence: mbo.sequence,
+                instrument_id,
+                publisher,
+                kind,
+            })
+        };
+
+        if self.validate {
+            if let Err(violation) = book.validate() {
+                push(BookEventKind::Invalid(violation));
+            }
+        }
+        let key = (instrument_id, publisher);
+        match book.crossing() {
+            Some(crossing) => {
+                if self.crossings.insert(key, crossing) != Some(crossing) {
+                    let (bid, ask) = book.bbo();
+                    push(BookEventKind::Crossing {
+                        crossing,
+                        bid_px: bid.map_or(0, |level| level.price),
+                        ask_px: ask.map_or(0, |level| level.price),
+                    });
+                }
+            }
+            None => {
+                if self.crossings.remove(&key).is_some() {
+                    push(BookEventKind::Uncrossed);
+                }
+            }
+        }
+        events
+    }
+}
+
+impl Display for BookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ts_recv={} seq={} instr_id={} publisher={}: ",
+            self.ts_recv, self.sequence, self.instrument_id, self.publisher
+        )?;
+        match &self.kind {
+            BookEventKind::Crossing {
+                crossing,
+                bid_px,
+                ask_px,
+            } => write!(
+                f,
+                "{crossing:?} book, bid {:.2} / ask {:.2}",
+                pretty::Px(*bid_px),
+                pretty::Px(*ask_px)
+            ),
+            BookEventKind::Uncrossed => write!(f, "book no longer locked or crossed"),
+            BookEventKind::Invalid(violation) => write!(f, "invalid book: {violation}"),
+        }
+    }
+}
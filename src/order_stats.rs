@@ -0,0 +1,441 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use databento::dbn::{Action, MboMsg, Publisher, Record, Side};
+use serde::Serialize;
+
+use crate::orderbook::{Book, Market};
+
+/// Distances from the BBO at or beyond this many levels share a bucket.
+const MAX_DISTANCE: u32 = 10;
+
+/// Order lifetime, queue and cancellation statistics gathered while replaying into a [`Market`].
+///
+/// Orders that were already resting when the replay started (snapshot adds) or that are still
+/// open at the end are not counted, since their lifetimes are incomplete.
+#[derive(Debug, Default)]
+pub struct OrderStats {
+    live: HashMap<OrderKey, LiveOrder>,
+    // Order ID at the front of each price level's queue
+    heads: HashMap<(BookKey, Side, i64), u64>,
+    books: HashMap<BookKey, BookStats>,
+}
+
+type BookKey = (u32, Publisher);
+type OrderKey = (u32, Publisher, u64);
+
+#[derive(Debug)]
+struct LiveOrder {
+    side: Side,
+    price: i64,
+    add_ts: u64,
+    from_snapshot: bool,
+    distance: u32,
+    orders_ahead: u32,
+    modifies: u32,
+    filled: u64,
+    // Filled size not yet removed by the cancel that follows each fill
+    unsettled: u64,
+    cancelled: u64,
+    head_since: Option<u64>,
+    time_at_head: u64,
+    reached_head: bool,
+}
+
+#[derive(Debug, Default)]
+struct BookStats {
+    orders_added: u64,
+    fully_filled: u64,
+    partially_filled: u64,
+    cancelled: u64,
+    filled_lifetimes: Vec<u64>,
+    cancelled_lifetimes: Vec<u64>,
+    time_at_head: Vec<u64>,
+    modifies: BTreeMap<u32, u64>,
+    by_distance: BTreeMap<u32, Outcomes>,
+    by_queue: BTreeMap<u32, Outcomes>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Outcomes {
+    orders: u64,
+    filled: u64,
+    cancelled: u64,
+}
+
+/// Summary of a set of durations in nanoseconds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Distribution {
+    pub count: u64,
+    pub mean_ns: f64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub max_ns: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DistanceRow {
+    /// Price levels between the order and the BBO on its side when added; the last bucket
+    /// includes everything further away.
+    pub levels_from_bbo: u32,
+    pub orders: u64,
+    pub cancelled: u64,
+    pub cancel_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueRow {
+    /// Range of orders ahead in the queue when added, inclusive.
+    pub orders_ahead_min: u32,
+    pub orders_ahead_max: u32,
+    pub orders: u64,
+    pub filled: u64,
+    pub fill_probability: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModifyStats {
+    pub mean: f64,
+    pub max: u32,
+    /// Number of orders by modify count.
+    pub histogram: BTreeMap<u32, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BookOrderReport {
+    pub instrument_id: u32,
+    pub publisher: Publisher,
+    pub orders_added: u64,
+    pub fully_filled: u64,
+    pub partially_filled: u64,
+    pub cancelled: u64,
+    /// Add to final removal of orders with at least one fill.
+    pub filled_lifetime: Distribution,
+    /// Add to final removal of orders without fills.
+    pub cancelled_lifetime: Distribution,
+    /// Time at the front of the price level's queue, for orders that got there.
+    pub time_at_head: Distribution,
+    pub modifies: ModifyStats,
+    pub by_distance: Vec<DistanceRow>,
+    pub by_queue_position: Vec<QueueRow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderStatsReport {
+    pub books: Vec<BookOrderReport>,
+    /// Orders still resting at the end of the replay.
+    pub open_orders: usize,
+}
+
+impl OrderStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `mbo` to `market`, updating the statistics of the orders it touches.
+    pub fn apply(&mut self, market: &mut Market, mbo: &MboMsg) {
+        let (Ok(publisher), Ok(action)) = (mbo.publisher(), mbo.action()) else {
+            market.apply(mbo.clone());
+            return;
+        };
+        let book_key = (mbo.hd.instrument_id, publisher);
+        let key = (mbo.hd.instrument_id, publisher, mbo.order_id);
+        let ts = mbo.hd.ts_event;
+        // Where the order rested before this record
+        let prev = market
+            .book(book_key.0, book_key.1)
+            .and_then(|book| book.order(mbo.order_id))
+            .and_then(|order| Some((order.side().ok()?, order.price)));
+
+        market.apply(mbo.clone());
+        if mbo.flags.is_tob() {
+            return;
+        }
+        let Some(book) = market.book(book_key.0, book_key.1) else {
+            return;
+        };
+
+        match action {
+            Action::Add => self.on_add(book, key, mbo),
+            Action::Modify => match prev {
+                Some((side, price)) => {
+                    if let Some(order) = self.live.get_mut(&key) {
+                        order.modifies += 1;
+                        if let Ok(new_side) = mbo.side() {
+                            order.side = new_side;
+                        }
+                        order.price = mbo.price;
+                    }
+                    self.refresh_head(book, book_key, side, price, ts);
+                    if let Ok(new_side) = mbo.side() {
+                        self.refresh_head(book, book_key, new_side, mbo.price, ts);
+                    }
+                }
+                // The book treats a modify of an unknown order as an add
+                None => self.on_add(book, key, mbo),
+            },
+            Action::Fill => {
+                if let Some(order) = self.live.get_mut(&key) {
+                    order.filled += mbo.size as u64;
+                    order.unsettled += mbo.size as u64;
+                }
+            }
+            Action::Cancel => {
+                if let Some(order) = self.live.get_mut(&key) {
+                    let settled = order.unsettled.min(mbo.size as u64);
+                    order.unsettled -= settled;
+                    order.cancelled += mbo.size as u64 - settled;
+                }
+                if let Some((side, price)) = prev {
+                    if book.order(mbo.order_id).is_none() {
+                        self.refresh_head(book, book_key, side, price, ts);
+                        self.remove(key, ts);
+                    }
+                }
+            }
+            Action::Clear => {
+                self.live.retain(|(instrument_id, publisher, _), _| {
+                    (*instrument_id, *publisher) != book_key
+                });
+                self.heads
+                    .retain(|(head_book, _, _), _| *head_book != book_key);
+            }
+            Action::Trade | Action::None => {}
+        }
+    }
+
+    pub fn report(&self) -> OrderStatsReport {
+        let mut books: Vec<BookOrderReport> = self
+            .books
+            .iter()
+            .map(|((instrument_id, publisher), stats)| stats.report(*instrument_id, *publisher))
+            .collect();
+        books.sort_by_key(|book| (book.instrument_id, book.publisher));
+        OrderStatsReport {
+            books,
+            open_orders: self.live.values().filter(|o| !o.from_snapshot).count(),
+        }
+    }
+
+    fn on_add(&mut self, book: &Book, key: OrderKey, mbo: &MboMsg) {
+        let Ok(side) = mbo.side() else {
+            return;
+        };
+        let book_key = (key.0, key.1);
+        let distance = match side {
+            Side::Bid => book
+                .bid_levels()
+                .take_while(|l| l.price > mbo.price)
+                .count(),
+            _ => book
+                .ask_levels()
+                .take_while(|l| l.price < mbo.price)
+                .count(),
+        };
+        let orders_ahead = book
+            .level_orders(side, mbo.price)
+            .take_while(|order| order.order_id != mbo.order_id)
+            .count();
+        let from_snapshot = mbo.flags.is_snapshot();
+        if !from_snapshot {
+            self.books.entry(book_key).or_default().orders_added += 1;
+        }
+        self.live.insert(
+            key,
+            LiveOrder {
+                side,
+                price: mbo.price,
+                add_ts: mbo.hd.ts_event,
+                from_snapshot,
+                distance: (distance as u32).min(MAX_DISTANCE),
+                orders_ahead: orders_ahead as u32,
+                modifies: 0,
+                filled: 0,
+                unsettled: 0,
+                cancelled: 0,
+                head_since: None,
+                time_at_head: 0,
+                reached_head: false,
+            },
+        );
+        self.refresh_head(book, book_key, side, mbo.price, mbo.hd.ts_event);
+    }
+
+    fn refresh_head(&mut self, book: &Book, book_key: BookKey, side: Side, price: i64, ts: u64) {
+        let head = book
+            .level_orders(side, price)
+            .next()
+            .map(|order| order.order_id);
+        let level_key = (book_key, side, price);
+        let prev = match head {
+            Some(head) => self.heads.insert(level_key, head),
+            None => self.heads.remove(&level_key),
+        };
+        if prev == head {
+            return;
+        }
+        if let Some(order) = prev.and_then(|id| self.live.get_mut(&(book_key.0, book_key.1, id))) {
+            if let Some(since) = order.head_since.take() {
+                order.time_at_head += ts.saturating_sub(since);
+            }
+        }
+        if let Some(order) = head.and_then(|id| self.live.get_mut(&(book_key.0, book_key.1, id))) {
+            // A modified order may still be the head of the level it left
+            if order.side == side && order.price == price && order.head_since.is_none() {
+                order.head_since = Some(ts);
+                order.reached_head = true;
+            }
+        }
+    }
+
+    fn remove(&mut self, key: OrderKey, ts: u64) {
+        let Some(mut order) = self.live.remove(&key) else {
+            return;
+        };
+        if order.from_snapshot {
+            return;
+        }
+        if let Some(since) = order.head_since.take() {
+            order.time_at_head += ts.saturating_sub(since);
+        }
+        let stats = self.books.entry((key.0, key.1)).or_default();
+        let lifetime = ts.saturating_sub(order.add_ts);
+        let filled = order.filled > 0;
+        if !filled {
+            stats.cancelled += 1;
+            stats.cancelled_lifetimes.push(lifetime);
+        } else {
+            if order.cancelled == 0 {
+                stats.fully_filled += 1;
+            } else {
+                stats.partially_filled += 1;
+            }
+            stats.filled_lifetimes.push(lifetime);
+        }
+        if order.reached_head {
+            stats.time_at_head.push(order.time_at_head);
+        }
+        *stats.modifies.entry(order.modifies).or_default() += 1;
+        stats
+            .by_distance
+            .entry(order.distance)
+            .or_default()
+            .record(filled);
+        stats
+            .by_queue
+            .entry(queue_bucket(order.orders_ahead))
+            .or_default()
+            .record(filled);
+    }
+}
+
+impl BookStats {
+    fn report(&self, instrument_id: u32, publisher: Publisher) -> BookOrderReport {
+        let orders: u64 = self.modifies.values().sum();
+        let total_modifies: u64 = self
+            .modifies
+            .iter()
+            .map(|(count, orders)| *count as u64 * orders)
+            .sum();
+        BookOrderReport {
+            instrument_id,
+            publisher,
+            orders_added: self.orders_added,
+            fully_filled: self.fully_filled,
+            partially_filled: self.partially_filled,
+            cancelled: self.cancelled,
+            filled_lifetime: Distribution::new(&self.filled_lifetimes),
+            cancelled_lifetime: Distribution::new(&self.cancelled_lifetimes),
+            time_at_head: Distribution::new(&self.time_at_head),
+            modifies: ModifyStats {
+                mean: ratio(total_modifies, orders),
+                max: self.modifies.keys().last().copied().unwrap_or_default(),
+                histogram: self.modifies.clone(),
+            },
+            by_distance: self
+                .by_distance
+                .iter()
+                .map(|(distance, outcomes)| DistanceRow {
+                    levels_from_bbo: *distance,
+                    orders: outcomes.orders,
+                    cancelled: outcomes.cancelled,
+                    cancel_rate: ratio(outcomes.cancelled, outcomes.orders),
+                })
+                .collect(),
+            by_queue_position: self
+                .by_queue
+                .iter()
+                .map(|(bucket, outcomes)| QueueRow {
+                    orders_ahead_min: *bucket,
+                    orders_ahead_max: if *bucket == 0 { 0 } else { bucket * 2 - 1 },
+                    orders: outcomes.orders,
+                    filled: outcomes.filled,
+                    fill_probability: ratio(outcomes.filled, outcomes.orders),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Outcomes {
+    fn record(&mut self, filled: bool) {
+        self.orders += 1;
+        if filled {
+            self.filled += 1;
+        } else {
+            self.cancelled += 1;
+        }
+    }
+}
+
+impl Distribution {
+    pub fn new(durations: &[u64]) -> Self {
+        if durations.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+        Self {
+            count: sorted.len() as u64,
+            mean_ns: sorted.iter().map(|d| *d as f64).sum::<f64>() / sorted.len() as f64,
+            p50_ns: percentile(0.5),
+            p90_ns: percentile(0.9),
+            p99_ns: percentile(0.99),
+            max_ns: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+impl OrderStatsReport {
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_json(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Power-of-two buckets: 0, 1, 2-3, 4-7, ...
+fn queue_bucket(orders_ahead: u32) -> u32 {
+    if orders_ahead == 0 {
+        0
+    } else {
+        1 << orders_ahead.ilog2()
+    }
+}
+
+fn ratio(num: u64, den: u64) -> f64 {
+    if den == 0 {
+        0.0
+    } else {
+        num as f64 / den as f64
+    }
+}
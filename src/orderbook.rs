@@ -1,13 +1,20 @@
 use std::{
     collections::{BTreeMap, HashMap, VecDeque},
+    ffi::c_char,
     fmt::Display,
 };
 
-use databento::dbn::{pretty, Action, BidAskPair, MboMsg, Publisher, Record, Side, UNDEF_PRICE};
+use databento::dbn::{
+    pretty, Action, BidAskPair, FlagSet, MboMsg, Publisher, Record, Side, UNDEF_PRICE,
+};
+
+use crate::common::clear_record;
 
 #[derive(Debug, Default)]
 pub struct Market {
     books: HashMap<u32, Vec<(Publisher, Book)>>,
+    journal: bool,
+    state_hash: bool,
 }
 
 #[derive(Debug, Default)]
@@ -15,6 +22,27 @@ pub struct Book {
     orders_by_id: HashMap<u64, (Side, i64)>,
     offers: BTreeMap<i64, Level>,
     bids: BTreeMap<i64, Level>,
+    journal: Option<HashMap<u64, Vec<JournalEntry>>>,
+    // Maintained on each apply only when enabled
+    state_hash: Option<u64>,
+}
+
+/// One MBO event that touched an order, as recorded by the optional order journal.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub ts_event: u64,
+    pub ts_recv: u64,
+    pub sequence: u32,
+    pub action: Action,
+    pub side: Side,
+    /// Price and size of the record itself; for a Clear, the side and price of the order it
+    /// removed.
+    pub price: i64,
+    pub size: u32,
+    /// Size still resting after the event; 0 once the order is removed.
+    pub remaining: u32,
+    /// Size ahead in the queue after the event; `None` once the order is removed.
+    pub queue_pos: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,19 +52,109 @@ pub struct PriceLevel {
     pub count: u32,
 }
 
+/// Internal inconsistency found by [`Book::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// An `orders_by_id` entry doesn't resolve to a resting order at its side and price.
+    UnresolvedOrder {
+        order_id: u64,
+        side: Side,
+        price: i64,
+    },
+    /// A resting order is missing from `orders_by_id` or indexed at another side or price.
+    UnindexedOrder {
+        order_id: u64,
+        side: Side,
+        price: i64,
+    },
+    /// More resting orders than `orders_by_id` entries, i.e. duplicated order IDs.
+    DuplicateOrders {
+        indexed: usize,
+        resting: usize,
+    },
+    EmptyLevel {
+        side: Side,
+        price: i64,
+    },
+    /// An order's price differs from its level's price.
+    PriceMismatch {
+        order_id: u64,
+        side: Side,
+        price: i64,
+    },
+    /// A top-of-book order shares its side with other orders.
+    MixedTob {
+        side: Side,
+    },
+    ZeroSize {
+        order_id: u64,
+        side: Side,
+        price: i64,
+    },
+    /// The state hash maintained with [`Book::with_state_hash`] differs from one computed from
+    /// scratch.
+    HashMismatch {
+        incremental: u64,
+        recomputed: u64,
+    },
+}
+
+/// A book whose best bid is at or above its best offer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossing {
+    Locked,
+    Crossed,
+}
+
 type Level = VecDeque<MboMsg>;
 
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
 impl Market {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Have the books keep a per-order event journal.
+    pub fn with_journal(mut self) -> Self {
+        self.journal = true;
+        self
+    }
+
+    /// Have the books maintain their state hash on each apply, see [`Book::state_hash`].
+    pub fn with_state_hash(mut self) -> Self {
+        self.state_hash = true;
+        self
+    }
+
+    /// Journal of `order_id` in every book that saw it.
+    pub fn order_journal(&self, order_id: u64) -> Vec<(u32, Publisher, &[JournalEntry])> {
+        let mut journals: Vec<_> = self
+            .books()
+            .filter_map(|(instrument_id, publisher, book)| {
+                Some((instrument_id, publisher, book.order_journal(order_id)?))
+            })
+            .collect();
+        journals.sort_by_key(|(instrument_id, publisher, _)| (*instrument_id, *publisher));
+        journals
+    }
+
     pub fn books_by_pub(&self, instrument_id: u32) -> Option<&[(Publisher, Book)]> {
         self.books
             .get(&instrument_id)
             .map(|pub_books| pub_books.as_slice())
     }
 
+    /// Every book in the market with its instrument ID and publisher.
+    pub fn books(&self) -> impl Iterator<Item = (u32, Publisher, &Book)> {
+        self.books.iter().flat_map(|(instrument_id, pub_books)| {
+            pub_books
+                .iter()
+                .map(move |(publisher, book)| (*instrument_id, *publisher, book))
+        })
+    }
+
     pub fn book(&self, instrument_id: u32, publisher: Publisher) -> Option<&Book> {
         let books = self.books.get(&instrument_id)?;
         books.iter().find_map(|(book_pub, book)| {
@@ -92,6 +210,41 @@ impl Market {
         (agg_bid, agg_ask)
     }
 
+    /// Top `level_count` bid and ask levels consolidated across publishers, best first.
+    pub fn aggregated_levels(
+        &self,
+        instrument_id: u32,
+        level_count: usize,
+    ) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        let mut bids: BTreeMap<i64, PriceLevel> = BTreeMap::new();
+        let mut asks: BTreeMap<i64, PriceLevel> = BTreeMap::new();
+        for (_, book) in self.books_by_pub(instrument_id).unwrap_or_default() {
+            // The consolidated top N can only come from each book's top N
+            for level in book.bid_levels().take(level_count) {
+                Self::merge_level(&mut bids, level);
+            }
+            for level in book.ask_levels().take(level_count) {
+                Self::merge_level(&mut asks, level);
+            }
+        }
+        (
+            bids.into_values().rev().take(level_count).collect(),
+            asks.into_values().take(level_count).collect(),
+        )
+    }
+
+    /// [`Book::snapshot_records`] of every book, ordered by instrument ID and publisher.
+    pub fn snapshot_records(&self, ts_recv: u64) -> Vec<MboMsg> {
+        let mut books: Vec<_> = self.books().collect();
+        books.sort_by_key(|(instrument_id, publisher, _)| (*instrument_id, *publisher));
+        books
+            .into_iter()
+            .flat_map(|(instrument_id, publisher, book)| {
+                book.snapshot_records(instrument_id, publisher, ts_recv)
+            })
+            .collect()
+    }
+
     pub fn apply(&mut self, mbo: MboMsg) {
         let publisher = mbo.publisher().unwrap();
         let books = self.books.entry(mbo.hd.instrument_id).or_default();
@@ -101,11 +254,28 @@ impl Market {
         {
             book
         } else {
-            books.push((publisher, Book::default()));
+            let mut book = Book::new();
+            if self.journal {
+                book = book.with_journal();
+            }
+            if self.state_hash {
+                book = book.with_state_hash();
+            }
+            books.push((publisher, book));
             &mut books.last_mut().unwrap().1
         };
         book.apply(mbo);
     }
+
+    fn merge_level(levels: &mut BTreeMap<i64, PriceLevel>, level: PriceLevel) {
+        levels
+            .entry(level.price)
+            .and_modify(|agg_level| {
+                agg_level.size += level.size;
+                agg_level.count += level.count;
+            })
+            .or_insert(level);
+    }
 }
 
 impl Book {
@@ -113,6 +283,24 @@ impl Book {
         Self::default()
     }
 
+    /// Record every event touching each order from now on, see [`Book::order_journal`].
+    pub fn with_journal(mut self) -> Self {
+        self.journal.get_or_insert_with(HashMap::new);
+        self
+    }
+
+    /// Maintain the state hash on each apply instead of computing it on every
+    /// [`Book::state_hash`] call.
+    pub fn with_state_hash(mut self) -> Self {
+        self.state_hash = Some(self.recompute_state_hash());
+        self
+    }
+
+    /// Events that touched `order_id`, oldest first, if the journal is enabled.
+    pub fn order_journal(&self, order_id: u64) -> Option<&[JournalEntry]> {
+        self.journal.as_ref()?.get(&order_id).map(Vec::as_slice)
+    }
+
     pub fn bbo(&self) -> (Option<PriceLevel>, Option<PriceLevel>) {
         (self.bid_level(0), self.ask_level(0))
     }
@@ -145,6 +333,25 @@ impl Book {
             .map(|orders| PriceLevel::new(px, orders.iter()))
     }
 
+    pub fn bid_levels(&self) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.bids
+            .iter()
+            // Reverse to get highest first
+            .rev()
+            .map(|(price, orders)| PriceLevel::new(*price, orders.iter()))
+    }
+
+    pub fn ask_levels(&self) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.offers
+            .iter()
+            .map(|(price, orders)| PriceLevel::new(*price, orders.iter()))
+    }
+
+    /// Resting orders at `price` on `side` in priority order.
+    pub fn level_orders(&self, side: Side, price: i64) -> impl Iterator<Item = &MboMsg> {
+        self.side_levels(side).get(&price).into_iter().flatten()
+    }
+
     pub fn order(&self, order_id: u64) -> Option<&MboMsg> {
         let (side, price) = self.orders_by_id.get(&order_id)?;
         let levels = self.side_levels(*side);
@@ -164,6 +371,93 @@ impl Book {
         )
     }
 
+    /// Check the internal consistency of the book.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        for (order_id, (side, price)) in &self.orders_by_id {
+            let resting = self
+                .side_levels(*side)
+                .get(price)
+                .is_some_and(|level| level.iter().any(|order| order.order_id == *order_id));
+            if !resting {
+                return Err(InvariantViolation::UnresolvedOrder {
+                    order_id: *order_id,
+                    side: *side,
+                    price: *price,
+                });
+            }
+        }
+        let mut resting = 0;
+        for side in [Side::Bid, Side::Ask] {
+            let levels = self.side_levels(side);
+            let has_tob = levels.values().flatten().any(|order| order.flags.is_tob());
+            if has_tob && levels.values().flatten().count() != 1 {
+                return Err(InvariantViolation::MixedTob { side });
+            }
+            for (price, level) in levels {
+                if level.is_empty() {
+                    return Err(InvariantViolation::EmptyLevel {
+                        side,
+                        price: *price,
+                    });
+                }
+                for order in level {
+                    let order_id = order.order_id;
+                    if order.price != *price {
+                        return Err(InvariantViolation::PriceMismatch {
+                            order_id,
+                            side,
+                            price: *price,
+                        });
+                    }
+                    if order.size == 0 {
+                        return Err(InvariantViolation::ZeroSize {
+                            order_id,
+                            side,
+                            price: *price,
+                        });
+                    }
+                    if order.flags.is_tob() {
+                        continue;
+                    }
+                    resting += 1;
+                    if self.orders_by_id.get(&order_id) != Some(&(side, *price)) {
+                        return Err(InvariantViolation::UnindexedOrder {
+                            order_id,
+                            side,
+                            price: *price,
+                        });
+                    }
+                }
+            }
+        }
+        if resting != self.orders_by_id.len() {
+            return Err(InvariantViolation::DuplicateOrders {
+                indexed: self.orders_by_id.len(),
+                resting,
+            });
+        }
+        if let Some(incremental) = self.state_hash {
+            let recomputed = self.recompute_state_hash();
+            if recomputed != incremental {
+                return Err(InvariantViolation::HashMismatch {
+                    incremental,
+                    recomputed,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the best bid is at (locked) or above (crossed) the best offer.
+    pub fn crossing(&self) -> Option<Crossing> {
+        let (bid, ask) = (self.bids.keys().next_back()?, self.offers.keys().next()?);
+        match bid.cmp(ask) {
+            std::cmp::Ordering::Less => None,
+            std::cmp::Ordering::Equal => Some(Crossing::Locked),
+            std::cmp::Ordering::Greater => Some(Crossing::Crossed),
+        }
+    }
+
     pub fn snapshot(&self, level_count: usize) -> Vec<BidAskPair> {
         (0..level_count)
             .map(|i| {
@@ -183,8 +477,82 @@ impl Book {
             .collect()
     }
 
+    /// Synthetic MBO records rebuilding the book from scratch, like a feed's snapshot: a
+    /// Clear, then an Add for each resting order in priority order, bids before asks. Every
+    /// record is flagged `F_SNAPSHOT` and the last one `F_LAST`. The Adds keep each order's
+    /// last `ts_event`; `ts_recv` is the snapshot's.
+    pub fn snapshot_records(
+        &self,
+        instrument_id: u32,
+        publisher: Publisher,
+        ts_recv: u64,
+    ) -> Vec<MboMsg> {
+        let mut flags = FlagSet::empty();
+        flags.set_snapshot();
+        let clear = clear_record(instrument_id, publisher as u16, ts_recv, flags, 0);
+        let orders = self
+            .bids
+            .values()
+            .rev()
+            .chain(self.offers.values())
+            .flatten()
+            .map(|order| MboMsg {
+                action: Action::Add as u8 as c_char,
+                flags,
+                ts_recv,
+                ..order.clone()
+            });
+        let mut records: Vec<MboMsg> = std::iter::once(clear).chain(orders).collect();
+        if let Some(last) = records.last_mut() {
+            last.flags.set_last();
+        }
+        records
+    }
+
+    /// Hash of the resting orders: updated incrementally on each apply with
+    /// [`Book::with_state_hash`], computed from every level otherwise.
+    ///
+    /// Each price level hashes to 64-bit FNV-1a over its side (`b'B'` or `b'A'`), its price
+    /// (i64 little-endian), then the `order_id` (u64 LE) and `size` (u32 LE) of each order
+    /// in priority order. The book's hash is the wrapping sum of its level hashes, so it
+    /// doesn't depend on how the book is stored and an empty book hashes to 0.
+    pub fn state_hash(&self) -> u64 {
+        self.state_hash
+            .unwrap_or_else(|| self.recompute_state_hash())
+    }
+
     pub fn apply(&mut self, mbo: MboMsg) {
         let action = mbo.action().unwrap();
+        let journaled = (self.journal.is_some()
+            && mbo.order_id != 0
+            && !mbo.flags.is_tob()
+            && !matches!(action, Action::Clear | Action::None))
+        .then(|| mbo.clone());
+        // A Clear removes every resting order, each of which gets a final entry
+        let cleared: Vec<MboMsg> = if self.journal.is_some() && action == Action::Clear {
+            self.bids
+                .values()
+                .chain(self.offers.values())
+                .flatten()
+                .map(|order| MboMsg {
+                    hd: mbo.hd.clone(),
+                    action: mbo.action,
+                    size: mbo.size,
+                    flags: mbo.flags,
+                    ts_recv: mbo.ts_recv,
+                    sequence: mbo.sequence,
+                    ..order.clone()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        // Levels the record may change and their hash before it's applied
+        let touched = self.state_hash.is_some().then(|| {
+            let levels = self.touched_levels(&mbo, action);
+            let hash = self.levels_hash(&levels);
+            (levels, hash)
+        });
         match action {
             Action::Modify => self.modify(mbo),
             Action::Trade | Action::Fill | Action::None => {}
@@ -192,6 +560,90 @@ impl Book {
             Action::Add => self.add(mbo),
             Action::Clear => self.clear(),
         }
+        if let (Some(state_hash), Some((levels, hash_before))) = (self.state_hash, touched) {
+            self.state_hash = Some(if action == Action::Clear {
+                0
+            } else {
+                state_hash
+                    .wrapping_sub(hash_before)
+                    .wrapping_add(self.levels_hash(&levels))
+            });
+        }
+        if let Some(mbo) = journaled {
+            self.record(action, &mbo);
+        }
+        for mbo in cleared {
+            self.record(action, &mbo);
+        }
+    }
+
+    /// Levels `mbo` may change, before and after it's applied.
+    fn touched_levels(&self, mbo: &MboMsg, action: Action) -> Vec<(Side, i64)> {
+        let mut levels = Vec::new();
+        match action {
+            // A Clear resets the hash to 0 without hashing what it removes
+            Action::Trade | Action::Fill | Action::None | Action::Clear => return levels,
+            Action::Add | Action::Cancel | Action::Modify => {}
+        }
+        let side = mbo.side().unwrap();
+        if mbo.flags.is_tob() {
+            // Top-of-book records replace the whole side
+            levels.extend(self.side_levels(side).keys().map(|price| (side, *price)));
+        } else if let Some(prev) = self.orders_by_id.get(&mbo.order_id) {
+            levels.push(*prev);
+        }
+        if !levels.contains(&(side, mbo.price)) {
+            levels.push((side, mbo.price));
+        }
+        levels
+    }
+
+    fn recompute_state_hash(&self) -> u64 {
+        [Side::Bid, Side::Ask]
+            .into_iter()
+            .flat_map(|side| {
+                self.side_levels(side)
+                    .iter()
+                    .map(move |(price, level)| Self::level_hash(side, *price, level))
+            })
+            .fold(0, u64::wrapping_add)
+    }
+
+    fn levels_hash(&self, levels: &[(Side, i64)]) -> u64 {
+        levels.iter().fold(0, |hash, (side, price)| {
+            let level_hash = self
+                .side_levels(*side)
+                .get(price)
+                .map_or(0, |level| Self::level_hash(*side, *price, level));
+            hash.wrapping_add(level_hash)
+        })
+    }
+
+    fn level_hash(side: Side, price: i64, level: &Level) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET, &[side as u8]);
+        hash = fnv1a(hash, &price.to_le_bytes());
+        for order in level {
+            hash = fnv1a(hash, &order.order_id.to_le_bytes());
+            hash = fnv1a(hash, &order.size.to_le_bytes());
+        }
+        hash
+    }
+
+    fn record(&mut self, action: Action, mbo: &MboMsg) {
+        let entry = JournalEntry {
+            ts_event: mbo.hd.ts_event,
+            ts_recv: mbo.ts_recv,
+            sequence: mbo.sequence,
+            action,
+            side: mbo.side().unwrap_or(Side::None),
+            price: mbo.price,
+            size: mbo.size,
+            remaining: self.order(mbo.order_id).map_or(0, |order| order.size),
+            queue_pos: self.queue_pos(mbo.order_id),
+        };
+        if let Some(journal) = self.journal.as_mut() {
+            journal.entry(mbo.order_id).or_default().push(entry);
+        }
     }
 
     fn clear(&mut self) {
@@ -375,3 +827,73 @@ impl Display for PriceLevel {
         )
     }
 }
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnresolvedOrder {
+                order_id,
+                side,
+                price,
+            } => write!(
+                f,
+                "order {order_id} indexed at {side:?} {:.2} is not resting there",
+                pretty::Px(*price)
+            ),
+            Self::UnindexedOrder {
+                order_id,
+                side,
+                price,
+            } => write!(
+                f,
+                "order {order_id} resting at {side:?} {:.2} is not indexed there",
+                pretty::Px(*price)
+            ),
+            Self::DuplicateOrders { indexed, resting } => write!(
+                f,
+                "{resting} resting orders but {indexed} indexed order IDs"
+            ),
+            Self::EmptyLevel { side, price } => {
+                write!(f, "empty {side:?} level at {:.2}", pretty::Px(*price))
+            }
+            Self::PriceMismatch {
+                order_id,
+                side,
+                price,
+            } => write!(
+                f,
+                "order {order_id} in {side:?} level {:.2} has a different price",
+                pretty::Px(*price)
+            ),
+            Self::MixedTob { side } => {
+                write!(f, "top-of-book order mixed with other {side:?} orders")
+            }
+            Self::ZeroSize {
+                order_id,
+                side,
+                price,
+            } => write!(
+                f,
+                "order {order_id} at {side:?} {:.2} has zero size",
+                pretty::Px(*price)
+            ),
+            Self::HashMismatch {
+                incremental,
+                recomputed,
+            } => write!(
+                f,
+                "state hash {incremental:016x} differs from recomputed {recomputed:016x}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
@@ -0,0 +1,380 @@
+//! Bounded per-client send queues for the streamers.
+//!
+//! The producer pushes encoded DBN (metadata, then whole records) into an [`Outbox`] and a
+//! writer task drains it to the client. When the queue is full the [`SlowConsumer`] policy
+//! decides whether the producer waits, the oldest records are dropped, or the client is
+//! disconnected, so one slow client can't hold up the others.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use databento::dbn::{self, HasRType, Record, WithTsOut};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::Notify,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+use crate::common::now_ns;
+
+/// Most bytes the writer takes from the queue per write.
+const MAX_BATCH_LEN: usize = 64 * 1024;
+
+/// What to do when a client's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowConsumer {
+    /// Wait for the client, holding up whatever feeds the queue.
+    Block,
+    /// Drop the oldest queued records and tell the client with a gap `SystemMsg`.
+    DropOldest,
+    /// Disconnect the client once its oldest queued record is older than the max lag.
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutboxConfig {
+    /// Most bytes queued per client. A single larger push is still accepted into an empty queue.
+    pub capacity: usize,
+    pub policy: SlowConsumer,
+    /// How far behind a [`SlowConsumer::Disconnect`] client may fall.
+    pub max_lag: Duration,
+}
+
+/// Per-client queue metrics.
+#[derive(Debug, Clone, Default)]
+pub struct OutboxStats {
+    pub sent_bytes: u64,
+    /// Bytes queued or being written now.
+    pub queued_bytes: usize,
+    pub max_queued_bytes: usize,
+    pub dropped_records: u64,
+    pub dropped_bytes: u64,
+    /// Gap notifications sent; each covers one or more dropped records.
+    pub gaps: u64,
+    /// Longest time from pushing bytes to finishing writing them.
+    pub max_delay: Duration,
+}
+
+/// Producer side of a client's queue. Dropping it without [`finish`](Self::finish) closes
+/// the connection immediately.
+pub struct Outbox {
+    config: OutboxConfig,
+    shared: Arc<Shared>,
+    writer: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Wakes the writer when bytes are pushed or the outbox is closed.
+    pushed: Notify,
+    /// Wakes the producer when bytes are written or the writer fails.
+    written: Notify,
+    /// Gap notifications are encoded in the stream's DBN version.
+    version: u8,
+    ts_out: bool,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Chunk>,
+    /// Push time of the oldest chunk being written.
+    writing_since: Option<Instant>,
+    /// Records dropped from the back of the queue, reported before the next chunk pushed.
+    pending_gap: u64,
+    closed: bool,
+    error: Option<String>,
+    stats: OutboxStats,
+}
+
+struct Chunk {
+    bytes: Vec<u8>,
+    /// Whole records in `bytes`; chunks without records (metadata) are never dropped.
+    records: u64,
+    /// Records dropped just before this chunk, reported in a gap notification ahead of it.
+    gap_before: u64,
+    at: Instant,
+}
+
+impl Outbox {
+    /// Start a writer task draining to `writer`, for a DBN stream of `version` whose records
+    /// carry `ts_out` if set.
+    pub fn spawn<W>(writer: W, config: OutboxConfig, version: u8, ts_out: bool) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            pushed: Notify::new(),
+            written: Notify::new(),
+            version,
+            ts_out,
+        });
+        let writer = tokio::spawn(write_loop(writer, Arc::clone(&shared)));
+        Self {
+            config,
+            shared,
+            writer: Some(writer),
+        }
+    }
+
+    /// Queue `bytes` holding `records` whole records, applying the slow consumer policy when
+    /// the queue is full. Fails once the client is gone or disconnected.
+    pub async fn push(&mut self, bytes: Vec<u8>, records: u64) -> anyhow::Result<()> {
+        let capacity = self.config.capacity;
+        let shared = Arc::clone(&self.shared);
+        loop {
+            let deadline = {
+                let mut state = shared.lock();
+                if let Some(error) = &state.error {
+                    anyhow::bail!("{error}");
+                }
+                if self.config.policy == SlowConsumer::Disconnect {
+                    if let Some(oldest) = state.oldest() {
+                        if oldest.elapsed() > self.config.max_lag {
+                            return self.disconnect(state);
+                        }
+                    }
+                }
+                let queued = state.stats.queued_bytes;
+                if queued == 0 || queued + bytes.len() <= capacity {
+                    state.enqueue(bytes, records);
+                    drop(state);
+                    shared.pushed.notify_one();
+                    return Ok(());
+                }
+                match self.config.policy {
+                    SlowConsumer::Block => None,
+                    SlowConsumer::DropOldest => {
+                        while state.stats.queued_bytes + bytes.len() > capacity
+                            && state.drop_oldest()
+                        {}
+                        state.enqueue(bytes, records);
+                        drop(state);
+                        shared.pushed.notify_one();
+                        return Ok(());
+                    }
+                    SlowConsumer::Disconnect => state.oldest().map(|at| at + self.config.max_lag),
+                }
+            };
+            let written = shared.written.notified();
+            match deadline {
+                Some(deadline) => {
+                    if time::timeout_at(deadline, written).await.is_err() {
+                        let state = shared.lock();
+                        return self.disconnect(state);
+                    }
+                }
+                None => written.await,
+            }
+        }
+    }
+
+    /// Queue one record.
+    pub async fn push_record<R: Record>(&mut self, record: &R) -> anyhow::Result<()> {
+        self.push(record.as_ref().to_vec(), 1).await
+    }
+
+    pub fn stats(&self) -> OutboxStats {
+        self.shared.lock().stats.clone()
+    }
+
+    /// Wait for everything queued to be written, then close the connection.
+    pub async fn finish(mut self) -> anyhow::Result<OutboxStats> {
+        self.shared.lock().closed = true;
+        self.shared.pushed.notify_one();
+        if let Some(writer) = self.writer.take() {
+            writer.await?;
+        }
+        let state = self.shared.lock();
+        match &state.error {
+            Some(error) => anyhow::bail!("{error}"),
+            None => Ok(state.stats.clone()),
+        }
+    }
+
+    fn disconnect(&mut self, mut state: MutexGuard<'_, State>) -> anyhow::Result<()> {
+        let error = format!(
+            "fell more than {:?} behind, disconnecting",
+            self.config.max_lag
+        );
+        state.error = Some(error.clone());
+        drop(state);
+        if let Some(writer) = self.writer.take() {
+            writer.abort();
+        }
+        anyhow::bail!("{error}")
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            writer.abort();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// A `SystemMsg` telling the client `dropped` records are missing.
+    fn gap_record(&self, dropped: u64) -> Vec<u8> {
+        let now = now_ns();
+        let msg = format!("Gap: dropped {dropped} records for a slow consumer");
+        let record = match self.version {
+            1 => dbn::v1::SystemMsg::new(now, &msg).map(|msg| record_bytes(&msg, self.ts_out)),
+            _ => dbn::SystemMsg::new(now, None, &msg).map(|msg| record_bytes(&msg, self.ts_out)),
+        };
+        record.unwrap_or_default()
+    }
+}
+
+impl State {
+    fn enqueue(&mut self, bytes: Vec<u8>, records: u64) {
+        self.stats.queued_bytes += bytes.len();
+        self.stats.max_queued_bytes = self.stats.max_queued_bytes.max(self.stats.queued_bytes);
+        self.queue.push_back(Chunk {
+            bytes,
+            records,
+            gap_before: std::mem::take(&mut self.pending_gap),
+            at: Instant::now(),
+        });
+    }
+
+    /// Drop the oldest chunk of records. Returns false if there's none.
+    fn drop_oldest(&mut self) -> bool {
+        let Some(idx) = self.queue.iter().position(|chunk| chunk.records > 0) else {
+            return false;
+        };
+        let chunk = self.queue.remove(idx).unwrap();
+        self.stats.queued_bytes -= chunk.bytes.len();
+        self.stats.dropped_records += chunk.records;
+        self.stats.dropped_bytes += chunk.bytes.len() as u64;
+        let dropped = chunk.gap_before + chunk.records;
+        match self.queue.get_mut(idx) {
+            Some(next) => next.gap_before += dropped,
+            None => self.pending_gap += dropped,
+        }
+        true
+    }
+
+    /// Push time of the oldest bytes not yet written.
+    fn oldest(&self) -> Option<Instant> {
+        self.writing_since
+            .or_else(|| self.queue.front().map(|chunk| chunk.at))
+    }
+}
+
+async fn write_loop<W: AsyncWrite + Unpin>(mut writer: W, shared: Arc<Shared>) {
+    let result = async {
+        'write: loop {
+            let (batch, payload_len, since) = loop {
+                let pushed = shared.pushed.notified();
+                {
+                    let mut state = shared.lock();
+                    if let Some(since) = state.queue.front().map(|chunk| chunk.at) {
+                        let (mut batch, mut payload_len) = (Vec::new(), 0);
+                        while let Some(chunk) = state.queue.front() {
+                            if !batch.is_empty() && batch.len() + chunk.bytes.len() > MAX_BATCH_LEN
+                            {
+                                break;
+                            }
+                            let chunk = state.queue.pop_front().unwrap();
+                            if chunk.gap_before > 0 {
+                                batch.extend(shared.gap_record(chunk.gap_before));
+                                state.stats.gaps += 1;
+                            }
+                            batch.extend_from_slice(&chunk.bytes);
+                            payload_len += chunk.bytes.len();
+                        }
+                        state.writing_since = Some(since);
+                        break (batch, payload_len, since);
+                    }
+                    if state.closed {
+                        break 'write;
+                    }
+                }
+                pushed.await;
+            };
+            writer.write_all(&batch).await?;
+            // Pushes buffered bytes out of compressing writers
+            writer.flush().await?;
+            let mut state = shared.lock();
+            state.writing_since = None;
+            state.stats.sent_bytes += batch.len() as u64;
+            state.stats.queued_bytes -= payload_len;
+            state.stats.max_delay = state.stats.max_delay.max(since.elapsed());
+            drop(state);
+            shared.written.notify_one();
+        }
+        writer.flush().await?;
+        writer.shutdown().await
+    }
+    .await;
+    if let Err(err) = result {
+        shared.lock().error = Some(format!("writing to client: {err}"));
+    }
+    shared.written.notify_one();
+}
+
+fn record_bytes<R: HasRType + Clone>(record: &R, ts_out: bool) -> Vec<u8> {
+    if ts_out {
+        WithTsOut::new(record.clone(), now_ns()).as_ref().to_vec()
+    } else {
+        record.as_ref().to_vec()
+    }
+}
+
+impl FromStr for SlowConsumer {
+    type Err = String;
+
+    /// Parse `block`, `drop-oldest` or `disconnect`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            "disconnect" => Ok(Self::Disconnect),
+            _ => Err(format!(
+                "unknown policy {s:?}, expected block, drop-oldest or disconnect"
+            )),
+        }
+    }
+}
+
+impl Display for SlowConsumer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Block => write!(f, "block"),
+            Self::DropOldest => write!(f, "drop-oldest"),
+            Self::Disconnect => write!(f, "disconnect"),
+        }
+    }
+}
+
+impl Display for OutboxStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes sent, at most {} bytes queued and {:?} behind",
+            self.sent_bytes, self.max_queued_bytes, self.max_delay
+        )?;
+        if self.dropped_records > 0 {
+            write!(
+                f,
+                ", {} records ({} bytes) dropped in {} gaps",
+                self.dropped_records, self.dropped_bytes, self.gaps
+            )?;
+        }
+        Ok(())
+    }
+}
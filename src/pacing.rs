@@ -0,0 +1,108 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use tokio::time::Instant;
+
+/// Deadline used when a record's scaled gap doesn't fit in an [`Instant`], about 30 years out.
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+/// Slowest replay speed [`Speed::from_str`] accepts.
+const MIN_SPEED_FACTOR: f64 = 1e-6;
+
+/// Replay speed relative to the recorded timestamps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// Multiple of real time, e.g. `0.5` for half speed.
+    Factor(f64),
+    /// As fast as possible.
+    Max,
+}
+
+/// Schedules records at their recorded gaps, scaled by a [`Speed`].
+///
+/// Deadlines are computed from the first record's timestamp and wall-clock instant rather than
+/// from the previous record, so time spent writing doesn't accumulate into drift over long
+/// replays.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    speed: Speed,
+    /// Longest wait between two records; longer quiet periods are cut short.
+    max_sleep: Option<Duration>,
+    /// The first record's timestamp and when it was released.
+    origin: Option<(u64, Instant)>,
+    /// Total wall-clock time cut from quiet periods by `max_sleep`.
+    skipped: Duration,
+}
+
+impl Pacer {
+    pub fn new(speed: Speed, max_sleep: Option<Duration>) -> Self {
+        Self {
+            speed,
+            max_sleep,
+            origin: None,
+            skipped: Duration::ZERO,
+        }
+    }
+
+    /// When the record with timestamp `ts` is due, or `None` if it's due now.
+    ///
+    /// Timestamps earlier than the latest seen so far are due immediately.
+    pub fn deadline(&mut self, ts: u64) -> Option<Instant> {
+        let Speed::Factor(factor) = self.speed else {
+            return None;
+        };
+        let now = Instant::now();
+        let (origin_ts, origin) = *self.origin.get_or_insert((ts, now));
+        let elapsed =
+            Duration::try_from_secs_f64(ts.saturating_sub(origin_ts) as f64 / 1e9 / factor)
+                .unwrap_or(Duration::MAX);
+        // Saturate at a far-future instant rather than overflow for tiny factors
+        let mut deadline = origin
+            .checked_add(elapsed)
+            .unwrap_or_else(|| now + FAR_FUTURE)
+            .checked_sub(self.skipped)?;
+        if let Some(cap) = self
+            .max_sleep
+            .and_then(|max_sleep| now.checked_add(max_sleep))
+        {
+            if deadline > cap {
+                self.skipped += deadline - cap;
+                deadline = cap;
+            }
+        }
+        (deadline > now).then_some(deadline)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = String;
+
+    /// Parse `max`, or a multiplier like `2`, `0.5x` or `10x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("max") {
+            return Ok(Self::Max);
+        }
+        let factor: f64 = s
+            .strip_suffix(['x', 'X'])
+            .unwrap_or(s)
+            .parse()
+            .map_err(|_| format!("invalid speed {s:?}, expected e.g. 0.5x, 10x or max"))?;
+        if !(factor.is_finite() && factor > 0.0) {
+            return Err(format!("speed must be positive, got {s:?}"));
+        }
+        if factor < MIN_SPEED_FACTOR {
+            return Err(format!(
+                "speed must be at least {MIN_SPEED_FACTOR}x, got {s:?}"
+            ));
+        }
+        Ok(Self::Factor(factor))
+    }
+}
+
+impl Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Factor(factor) => write!(f, "{factor}x"),
+            Self::Max => write!(f, "max"),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! Python bindings mirroring `mbo_tools/order_book.py`, built with `--features python`.
+
+use std::collections::HashMap;
+
+use databento::dbn::{MboMsg, Publisher};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    common::{mbo_from_bytes, mbo_records, to_px},
+    dump::{BookDump, MarketDump},
+    lint::Linter,
+    orderbook::{self, Market},
+};
+
+#[pymodule]
+fn mbo_orderbook(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMarket>()?;
+    m.add_class::<PyBook>()?;
+    m.add_class::<PyPriceLevel>()?;
+    m.add_class::<PyBidAskPair>()?;
+    m.add_class::<PyOrder>()?;
+    Ok(())
+}
+
+/// Order books for every instrument and publisher, like Python's `Market`.
+#[pyclass(name = "Market", module = "mbo_orderbook")]
+#[derive(Default)]
+struct PyMarket {
+    market: Market,
+}
+
+/// A view of one book in a [`PyMarket`]; missing books read as empty, like Python's `Book`.
+#[pyclass(name = "Book", module = "mbo_orderbook", frozen)]
+struct PyBook {
+    market: Py<PyMarket>,
+    instrument_id: u32,
+    publisher: Publisher,
+}
+
+#[pyclass(name = "PriceLevel", module = "mbo_orderbook", frozen, eq)]
+#[derive(Clone, PartialEq)]
+struct PyPriceLevel {
+    #[pyo3(get)]
+    price: i64,
+    #[pyo3(get)]
+    size: u32,
+    #[pyo3(get)]
+    count: u32,
+}
+
+#[pyclass(name = "BidAskPair", module = "mbo_orderbook", frozen)]
+struct PyBidAskPair {
+    #[pyo3(get)]
+    bid_px: i64,
+    #[pyo3(get)]
+    ask_px: i64,
+    #[pyo3(get)]
+    bid_sz: u32,
+    #[pyo3(get)]
+    ask_sz: u32,
+    #[pyo3(get)]
+    bid_ct: u32,
+    #[pyo3(get)]
+    ask_ct: u32,
+}
+
+/// A resting order, with the `databento.MBOMsg` attribute names.
+#[pyclass(name = "Order", module = "mbo_orderbook", frozen)]
+struct PyOrder {
+    #[pyo3(get)]
+    ts_event: u64,
+    #[pyo3(get)]
+    ts_recv: u64,
+    #[pyo3(get)]
+    instrument_id: u32,
+    #[pyo3(get)]
+    publisher_id: u16,
+    #[pyo3(get)]
+    order_id: u64,
+    #[pyo3(get)]
+    price: i64,
+    #[pyo3(get)]
+    size: u32,
+    #[pyo3(get)]
+    side: char,
+    #[pyo3(get)]
+    action: char,
+    #[pyo3(get)]
+    flags: u8,
+    #[pyo3(get)]
+    channel_id: u8,
+    #[pyo3(get)]
+    sequence: u32,
+}
+
+#[pymethods]
+impl PyMarket {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a `databento.MBOMsg` (or any record supporting `bytes()`) or raw DBN bytes.
+    fn apply(&mut self, msg: &Bound<'_, PyAny>) -> PyResult<()> {
+        if let Ok(data) = msg.extract::<&[u8]>() {
+            self.apply_bytes(data)?;
+            return Ok(());
+        }
+        let raw = msg.call_method0("__bytes__")?;
+        let mbo = mbo_from_bytes(raw.extract()?)
+            .ok_or_else(|| PyValueError::new_err("not an MBO record"))?;
+        self.apply_mbo(mbo)
+    }
+
+    /// Apply the MBO records in a DBN stream or in concatenated raw DBN records, skipping
+    /// other record types. Returns the number of records applied.
+    fn apply_bytes(&mut self, data: &[u8]) -> PyResult<usize> {
+        let records = mbo_records(data).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let count = records.len();
+        for mbo in records {
+            self.apply_mbo(mbo)?;
+        }
+        Ok(count)
+    }
+
+    fn get_book(slf: &Bound<'_, Self>, instrument_id: u32, publisher_id: u16) -> PyResult<PyBook> {
+        Ok(PyBook {
+            market: slf.clone().unbind(),
+            instrument_id,
+            publisher: publisher(publisher_id)?,
+        })
+    }
+
+    fn get_books_by_pub(slf: &Bound<'_, Self>, instrument_id: u32) -> HashMap<u16, PyBook> {
+        let market = slf.borrow();
+        let books = market
+            .market
+            .books_by_pub(instrument_id)
+            .unwrap_or_default();
+        books
+            .iter()
+            .map(|(publisher, _)| {
+                let book = PyBook {
+                    market: slf.clone().unbind(),
+                    instrument_id,
+                    publisher: *publisher,
+                };
+                (*publisher as u16, book)
+            })
+            .collect()
+    }
+
+    fn bbo(
+        &self,
+        instrument_id: u32,
+        publisher_id: u16,
+    ) -> PyResult<(Option<PyPriceLevel>, Option<PyPriceLevel>)> {
+        let (bid, ask) = self.market.bbo(instrument_id, publisher(publisher_id)?);
+        Ok((bid.map(Into::into), ask.map(Into::into)))
+    }
+
+    fn aggregated_bbo(&self, instrument_id: u32) -> (Option<PyPriceLevel>, Option<PyPriceLevel>) {
+        let (bid, ask) = self.market.aggregated_bbo(instrument_id);
+        (bid.map(Into::into), ask.map(Into::into))
+    }
+
+    #[pyo3(signature = (include_orders=false))]
+    fn to_dict<'py>(&self, py: Python<'py>, include_orders: bool) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &MarketDump::new(&self.market, include_orders))
+    }
+}
+
+impl PyMarket {
+    fn apply_mbo(&mut self, mbo: MboMsg) -> PyResult<()> {
+        // `Market::apply` panics on unknown publishers and on records inconsistent with the
+        // book, e.g. a duplicate add, so those raise before anything is mutated
+        publisher(mbo.hd.publisher_id)?;
+        let (apply, anomalies) = Linter::check_record(&self.market, &mbo);
+        if !apply {
+            return Err(PyValueError::new_err(format!(
+                "failed to apply MBO record: {anomalies:?}"
+            )));
+        }
+        self.market.apply(mbo);
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PyBook {
+    fn __repr__(&self) -> String {
+        format!(
+            "Book(instrument_id={}, publisher_id={})",
+            self.instrument_id, self.publisher as u16
+        )
+    }
+
+    #[getter]
+    fn instrument_id(&self) -> u32 {
+        self.instrument_id
+    }
+
+    #[getter]
+    fn publisher_id(&self) -> u16 {
+        self.publisher as u16
+    }
+
+    fn bbo(&self, py: Python<'_>) -> (Option<PyPriceLevel>, Option<PyPriceLevel>) {
+        self.with_book(py, |book| {
+            let (bid, ask) = book.map(|book| book.bbo()).unwrap_or_default();
+            (bid.map(Into::into), ask.map(Into::into))
+        })
+    }
+
+    #[pyo3(signature = (idx=0))]
+    fn get_bid_level(&self, py: Python<'_>, idx: usize) -> Option<PyPriceLevel> {
+        self.with_book(py, |book| book?.bid_level(idx).map(Into::into))
+    }
+
+    #[pyo3(signature = (idx=0))]
+    fn get_ask_level(&self, py: Python<'_>, idx: usize) -> Option<PyPriceLevel> {
+        self.with_book(py, |book| book?.ask_level(idx).map(Into::into))
+    }
+
+    fn get_bid_level_by_px(&self, py: Python<'_>, px: i64) -> Option<PyPriceLevel> {
+        self.with_book(py, |book| book?.bid_level_by_px(px).map(Into::into))
+    }
+
+    fn get_ask_level_by_px(&self, py: Python<'_>, px: i64) -> Option<PyPriceLevel> {
+        self.with_book(py, |book| book?.ask_level_by_px(px).map(Into::into))
+    }
+
+    fn get_order(&self, py: Python<'_>, id: u64) -> Option<PyOrder> {
+        self.with_book(py, |book| book?.order(id).map(Into::into))
+    }
+
+    fn get_queue_pos(&self, py: Python<'_>, id: u64) -> Option<u32> {
+        self.with_book(py, |book| book?.queue_pos(id))
+    }
+
+    #[pyo3(signature = (level_count=1))]
+    fn get_snapshot(&self, py: Python<'_>, level_count: usize) -> Vec<PyBidAskPair> {
+        self.with_book(py, |book| {
+            let snapshot = match book {
+                Some(book) => book.snapshot(level_count),
+                None => vec![Default::default(); level_count],
+            };
+            snapshot
+                .into_iter()
+                .map(|pair| PyBidAskPair {
+                    bid_px: pair.bid_px,
+                    ask_px: pair.ask_px,
+                    bid_sz: pair.bid_sz,
+                    ask_sz: pair.ask_sz,
+                    bid_ct: pair.bid_ct,
+                    ask_ct: pair.ask_ct,
+                })
+                .collect()
+        })
+    }
+
+    fn state_hash(&self, py: Python<'_>) -> u64 {
+        self.with_book(py, |book| book.map_or(0, |book| book.state_hash()))
+    }
+
+    #[pyo3(signature = (include_orders=false))]
+    fn to_dict<'py>(&self, py: Python<'py>, include_orders: bool) -> PyResult<Bound<'py, PyAny>> {
+        let dump = self.with_book(py, |book| {
+            let empty = orderbook::Book::new();
+            BookDump::new(book.unwrap_or(&empty), include_orders)
+        });
+        to_py(py, &dump)
+    }
+}
+
+impl PyBook {
+    fn with_book<T>(&self, py: Python<'_>, f: impl FnOnce(Option<&orderbook::Book>) -> T) -> T {
+        let market = self.market.borrow(py);
+        f(market.market.book(self.instrument_id, self.publisher))
+    }
+}
+
+#[pymethods]
+impl PyPriceLevel {
+    #[new]
+    #[pyo3(signature = (price, size=0, count=0))]
+    fn new(price: i64, size: u32, count: u32) -> Self {
+        Self { price, size, count }
+    }
+
+    fn __str__(&self) -> String {
+        orderbook::PriceLevel::from(self.clone()).to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PriceLevel(price={}, size={}, count={})",
+            self.price, self.size, self.count
+        )
+    }
+}
+
+#[pymethods]
+impl PyBidAskPair {
+    fn __repr__(&self) -> String {
+        format!(
+            "BidAskPair(bid_px={}, ask_px={}, bid_sz={}, ask_sz={}, bid_ct={}, ask_ct={})",
+            self.bid_px, self.ask_px, self.bid_sz, self.ask_sz, self.bid_ct, self.ask_ct
+        )
+    }
+}
+
+#[pymethods]
+impl PyOrder {
+    #[getter]
+    fn pretty_price(&self) -> f64 {
+        to_px(self.price)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Order(order_id={}, side={:?}, price={}, size={}, instrument_id={}, publisher_id={})",
+            self.order_id, self.side, self.price, self.size, self.instrument_id, self.publisher_id
+        )
+    }
+}
+
+impl From<orderbook::PriceLevel> for PyPriceLevel {
+    fn from(level: orderbook::PriceLevel) -> Self {
+        Self {
+            price: level.price,
+            size: level.size,
+            count: level.count,
+        }
+    }
+}
+
+impl From<PyPriceLevel> for orderbook::PriceLevel {
+    fn from(level: PyPriceLevel) -> Self {
+        Self {
+            price: level.price,
+            size: level.size,
+            count: level.count,
+        }
+    }
+}
+
+impl From<&MboMsg> for PyOrder {
+    fn from(mbo: &MboMsg) -> Self {
+        Self {
+            ts_event: mbo.hd.ts_event,
+            ts_recv: mbo.ts_recv,
+            instrument_id: mbo.hd.instrument_id,
+            publisher_id: mbo.hd.publisher_id,
+            order_id: mbo.order_id,
+            price: mbo.price,
+            size: mbo.size,
+            side: mbo.side as u8 as char,
+            action: mbo.action as u8 as char,
+            flags: mbo.flags.raw(),
+            channel_id: mbo.channel_id,
+            sequence: mbo.sequence,
+        }
+    }
+}
+
+fn publisher(publisher_id: u16) -> PyResult<Publisher> {
+    Publisher::try_from(publisher_id)
+        .map_err(|_| PyValueError::new_err(format!("unknown publisher ID {publisher_id}")))
+}
+
+/// Convert a dump to plain Python dicts and lists by way of JSON.
+fn to_py<'py>(py: Python<'py>, value: &impl serde::Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_string(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
@@ -0,0 +1,110 @@
+//! Replaying one captured session over and over as a single continuous feed.
+
+use std::collections::BTreeSet;
+
+use databento::dbn::{FlagSet, MboMsg, UNDEF_TIMESTAMP};
+
+use crate::common::clear_record;
+
+/// Shifts the timestamps and sequence numbers of a file replayed in a loop so they keep
+/// moving forward, and ends each loop with a Clear of every book it touched.
+///
+/// Each loop starts 1ns after the previous one ended, so gaps between records are kept and a
+/// [`Pacer`](crate::pacing::Pacer) runs across loop boundaries without a pause. Sequence
+/// numbers wrap around at `u32::MAX`. The first loop is shifted by the offset the rebaser is
+/// created with, e.g. to move it to the current time.
+#[derive(Debug, Clone, Default)]
+pub struct Rebaser {
+    /// Added to `ts_event` and `ts_recv` in the current loop.
+    ts_offset: u64,
+    /// Added to `sequence` in the current loop.
+    sequence_offset: u32,
+    /// Earliest and latest timestamps in the file, before rebasing.
+    ts_range: Option<(u64, u64)>,
+    /// Lowest and highest sequence numbers in the file, before rebasing.
+    sequence_range: Option<(u32, u32)>,
+    /// Books seen, by instrument ID and publisher ID.
+    books: BTreeSet<(u32, u16)>,
+    loops: u64,
+}
+
+impl Rebaser {
+    /// A rebaser adding `ts_offset` to the timestamps of the first loop.
+    pub fn new(ts_offset: u64) -> Self {
+        Self {
+            ts_offset,
+            ..Self::default()
+        }
+    }
+
+    /// Added to `ts_event` and `ts_recv` in the current loop.
+    pub fn ts_offset(&self) -> u64 {
+        self.ts_offset
+    }
+
+    /// Whether no record has been rebased yet.
+    pub fn is_empty(&self) -> bool {
+        self.ts_range.is_none()
+    }
+
+    /// How many loops have ended.
+    pub fn loops(&self) -> u64 {
+        self.loops
+    }
+
+    /// `mbo` moved into the current loop.
+    pub fn rebase(&mut self, mbo: &MboMsg) -> MboMsg {
+        let mut mbo = mbo.clone();
+        for ts in [&mut mbo.hd.ts_event, &mut mbo.ts_recv] {
+            if *ts != UNDEF_TIMESTAMP {
+                widen(&mut self.ts_range, *ts);
+                *ts += self.ts_offset;
+            }
+        }
+        widen(&mut self.sequence_range, mbo.sequence);
+        mbo.sequence = mbo.sequence.wrapping_add(self.sequence_offset);
+        self.books
+            .insert((mbo.hd.instrument_id, mbo.hd.publisher_id));
+        mbo
+    }
+
+    /// End the current loop: a Clear of every book seen, stamped with the loop's latest
+    /// timestamp and the sequence numbers following it. The next loop starts right after.
+    pub fn end_loop(&mut self) -> Vec<MboMsg> {
+        let Some((first_ts, last_ts)) = self.ts_range else {
+            return Vec::new();
+        };
+        let (first_sequence, last_sequence) = self.sequence_range.unwrap_or_default();
+        let end = last_ts + self.ts_offset;
+        let clears: Vec<MboMsg> = self
+            .books
+            .iter()
+            .zip(1..)
+            .map(|(&(instrument_id, publisher_id), idx)| {
+                let sequence = last_sequence
+                    .wrapping_add(self.sequence_offset)
+                    .wrapping_add(idx);
+                clear_record(
+                    instrument_id,
+                    publisher_id,
+                    end,
+                    FlagSet::empty().set_last(),
+                    sequence,
+                )
+            })
+            .collect();
+        self.ts_offset += last_ts - first_ts + 1;
+        self.sequence_offset = self
+            .sequence_offset
+            .wrapping_add(last_sequence - first_sequence)
+            .wrapping_add(clears.len() as u32 + 1);
+        self.loops += 1;
+        clears
+    }
+}
+
+fn widen<T: Copy + Ord>(range: &mut Option<(T, T)>, value: T) {
+    let (low, high) = range.get_or_insert((value, value));
+    *low = (*low).min(value);
+    *high = (*high).max(value);
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::HashMap,
+    ffi::c_char,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use databento::dbn::{
+    encode::{DbnEncoder, EncodeRecord},
+    rtype, Action, BidAskPair, FlagSet, MboMsg, Mbp10Msg, Metadata, Publisher, Record,
+    RecordHeader, Schema, Side, UNDEF_PRICE,
+};
+use serde::Serialize;
+
+use crate::{common::to_px, orderbook::Market};
+
+/// When the books are sampled.
+#[derive(Debug, Clone, Copy)]
+pub enum Trigger {
+    /// Every `interval_ns` of `ts_event`, aligned to multiples of the interval.
+    Interval { interval_ns: u64 },
+    /// After every event (record flagged `F_LAST`) of the book.
+    Event,
+    /// After every `count` trades of the book.
+    Trades { count: u64 },
+}
+
+/// Top-N depth of one book at a point in time.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// Sample time: the interval boundary, or the `ts_event` of the triggering record.
+    pub ts_event: u64,
+    /// `ts_recv` of the last record applied before the sample was taken.
+    pub ts_recv: u64,
+    pub instrument_id: u32,
+    pub publisher: Publisher,
+    pub sequence: u32,
+    pub levels: Vec<BidAskPair>,
+}
+
+/// Replays records through a [`Market`] and snapshots its books on a [`Trigger`].
+#[derive(Debug)]
+pub struct Sampler {
+    trigger: Trigger,
+    level_count: usize,
+    next_boundary: Option<u64>,
+    last_ts_recv: u64,
+    // Trades since the last sample, per instrument and publisher
+    trades: HashMap<(u32, Publisher), u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Csv,
+    JsonLines,
+    /// DBN MBP-10 records, keeping at most 10 levels.
+    Mbp10,
+}
+
+pub enum SampleWriter {
+    Csv(BufWriter<File>),
+    JsonLines(BufWriter<File>),
+    Mbp10(DbnEncoder<BufWriter<File>>),
+}
+
+#[derive(Serialize)]
+struct JsonSample {
+    ts_event: u64,
+    ts_recv: u64,
+    instrument_id: u32,
+    publisher_id: u16,
+    sequence: u32,
+    levels: Vec<JsonLevel>,
+}
+
+#[derive(Serialize)]
+struct JsonLevel {
+    bid_px: Option<f64>,
+    bid_sz: u32,
+    bid_ct: u32,
+    ask_px: Option<f64>,
+    ask_sz: u32,
+    ask_ct: u32,
+}
+
+impl Sampler {
+    pub fn new(trigger: Trigger, level_count: usize) -> Self {
+        Self {
+            trigger,
+            level_count,
+            next_boundary: None,
+            last_ts_recv: 0,
+            trades: HashMap::new(),
+        }
+    }
+
+    /// Apply `mbo` to `market` and return the samples it triggered.
+    ///
+    /// Interval samples are taken before `mbo` is applied, so they reflect the
+    /// books as of the boundary.
+    pub fn apply(&mut self, market: &mut Market, mbo: &MboMsg) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        if let Trigger::Interval { interval_ns } = self.trigger {
+            let interval_ns = interval_ns.max(1);
+            let ts = mbo.hd.ts_event;
+            let next = *self
+                .next_boundary
+                .get_or_insert(ts - ts % interval_ns + interval_ns);
+            let mut boundary = next;
+            while boundary <= ts {
+                samples.extend(self.sample_all(market, boundary));
+                boundary += interval_ns;
+            }
+            self.next_boundary = Some(boundary);
+        }
+
+        market.apply(mbo.clone());
+        self.last_ts_recv = mbo.ts_recv;
+
+        let Ok(publisher) = mbo.publisher() else {
+            return samples;
+        };
+        let key = (mbo.hd.instrument_id, publisher);
+        if matches!(mbo.action(), Ok(Action::Trade)) {
+            *self.trades.entry(key).or_default() += 1;
+        }
+        if !mbo.flags.is_last() {
+            return samples;
+        }
+        let triggered = match self.trigger {
+            Trigger::Interval { .. } => false,
+            Trigger::Event => true,
+            Trigger::Trades { count } => self.trades.get(&key).is_some_and(|n| *n >= count),
+        };
+        if triggered {
+            self.trades.remove(&key);
+            if let Some(book) = market.book(key.0, key.1) {
+                samples.push(Sample {
+                    ts_event: mbo.hd.ts_event,
+                    ts_recv: mbo.ts_recv,
+                    instrument_id: key.0,
+                    publisher,
+                    sequence: mbo.sequence,
+                    levels: book.snapshot(self.level_count),
+                });
+            }
+        }
+        samples
+    }
+
+    fn sample_all(&self, market: &Market, ts: u64) -> Vec<Sample> {
+        let mut samples: Vec<Sample> = market
+            .books()
+            .map(|(instrument_id, publisher, book)| Sample {
+                ts_event: ts,
+                ts_recv: self.last_ts_recv,
+                instrument_id,
+                publisher,
+                sequence: 0,
+                levels: book.snapshot(self.level_count),
+            })
+            .collect();
+        samples.sort_by_key(|sample| (sample.instrument_id, sample.publisher));
+        samples
+    }
+}
+
+impl SampleWriter {
+    /// `metadata` of the input is used as the template for the MBP-10 output.
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: SampleFormat,
+        level_count: usize,
+        metadata: &Metadata,
+    ) -> anyhow::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        Ok(match format {
+            SampleFormat::Csv => {
+                write!(file, "ts_event,ts_recv,instrument_id,publisher_id")?;
+                for i in 0..level_count {
+                    write!(
+                        file,
+                        ",bid_px_{i:02},bid_sz_{i:02},bid_ct_{i:02},ask_px_{i:02},ask_sz_{i:02},ask_ct_{i:02}"
+                    )?;
+                }
+                writeln!(file)?;
+                Self::Csv(file)
+            }
+            SampleFormat::JsonLines => Self::JsonLines(file),
+            SampleFormat::Mbp10 => {
+                let mut metadata = metadata.clone();
+                metadata.schema = Some(Schema::Mbp10);
+                Self::Mbp10(DbnEncoder::new(file, &metadata)?)
+            }
+        })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(file) => {
+                write!(
+                    file,
+                    "{},{},{},{}",
+                    sample.ts_event, sample.ts_recv, sample.instrument_id, sample.publisher as u16
+                )?;
+                for level in &sample.levels {
+                    write!(
+                        file,
+                        ",{},{},{},{},{},{}",
+                        fmt_px(level.bid_px),
+                        level.bid_sz,
+                        level.bid_ct,
+                        fmt_px(level.ask_px),
+                        level.ask_sz,
+                        level.ask_ct
+                    )?;
+                }
+                writeln!(file)?;
+            }
+            Self::JsonLines(file) => {
+                let json = JsonSample {
+                    ts_event: sample.ts_event,
+                    ts_recv: sample.ts_recv,
+                    instrument_id: sample.instrument_id,
+                    publisher_id: sample.publisher as u16,
+                    sequence: sample.sequence,
+                    levels: sample
+                        .levels
+                        .iter()
+                        .map(|level| JsonLevel {
+                            bid_px: level_px(level.bid_px),
+                            bid_sz: level.bid_sz,
+                            bid_ct: level.bid_ct,
+                            ask_px: level_px(level.ask_px),
+                            ask_sz: level.ask_sz,
+                            ask_ct: level.ask_ct,
+                        })
+                        .collect(),
+                };
+                serde_json::to_writer(&mut *file, &json)?;
+                writeln!(file)?;
+            }
+            Self::Mbp10(encoder) => encoder.encode_record(&sample.to_mbp10())?,
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(mut file) | Self::JsonLines(mut file) => file.flush()?,
+            Self::Mbp10(mut encoder) => encoder.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Sample {
+    /// Convert to an MBP-10 record with no triggering action.
+    pub fn to_mbp10(&self) -> Mbp10Msg {
+        let mut levels: [BidAskPair; 10] = Default::default();
+        for (level, sampled) in levels.iter_mut().zip(&self.levels) {
+            *level = sampled.clone();
+        }
+        Mbp10Msg {
+            hd: RecordHeader::new::<Mbp10Msg>(
+                rtype::MBP_10,
+                self.publisher as u16,
+                self.instrument_id,
+                self.ts_event,
+            ),
+            price: UNDEF_PRICE,
+            size: 0,
+            action: Action::None as u8 as c_char,
+            side: Side::None as u8 as c_char,
+            flags: FlagSet::empty().set_last(),
+            depth: 0,
+            ts_recv: self.ts_recv,
+            ts_in_delta: 0,
+            sequence: self.sequence,
+            levels,
+        }
+    }
+}
+
+fn level_px(price: i64) -> Option<f64> {
+    (price != UNDEF_PRICE).then(|| to_px(price))
+}
+
+fn fmt_px(price: i64) -> String {
+    level_px(price).map(|px| px.to_string()).unwrap_or_default()
+}
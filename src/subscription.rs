@@ -0,0 +1,163 @@
+//! Subscription handshake spoken by `mbo-streamer` and `mbo-consumer`.
+//!
+//! The client sends one line, e.g. `SUBSCRIBE symbols=CLX5,CLZ5 schema=mbo start=1760097600000000000 speed=2x`,
+//! and the server answers `OK` or `ERR <reason>` before sending `Metadata` and records. Every
+//! field is optional. Clients that send nothing are served the default stream.
+
+use std::{fmt::Display, str::FromStr};
+
+use databento::dbn::{Metadata, Schema};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{filter::RecordFilter, pacing::Speed};
+
+/// Longest control line either side accepts.
+const MAX_LINE_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subscription {
+    /// Raw symbols; empty for every symbol.
+    pub symbols: Vec<String>,
+    pub schema: Option<Schema>,
+    /// Skip records with an earlier `ts_event`, in UNIX nanoseconds.
+    pub start: Option<u64>,
+    /// Replay speed; the server's default when `None`.
+    pub speed: Option<Speed>,
+}
+
+/// The server's answer to a [`Subscription`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Ok,
+    Err(String),
+}
+
+impl Subscription {
+    /// The records this subscription selects from a file with `metadata`.
+    pub fn filter(&self, metadata: &Metadata) -> anyhow::Result<RecordFilter> {
+        if let Some(schema) = self.schema.filter(|schema| *schema != Schema::Mbo) {
+            anyhow::bail!("unsupported schema {schema}, only mbo is available");
+        }
+        let filter = RecordFilter {
+            start: self.start,
+            ..RecordFilter::default()
+        };
+        filter.with_symbols(metadata, &self.symbols)
+    }
+}
+
+impl Display for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SUBSCRIBE")?;
+        if !self.symbols.is_empty() {
+            write!(f, " symbols={}", self.symbols.join(","))?;
+        }
+        if let Some(schema) = self.schema {
+            write!(f, " schema={schema}")?;
+        }
+        if let Some(start) = self.start {
+            write!(f, " start={start}")?;
+        }
+        if let Some(speed) = self.speed {
+            write!(f, " speed={speed}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Subscription {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> anyhow::Result<Self> {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some("SUBSCRIBE") {
+            anyhow::bail!("expected SUBSCRIBE");
+        }
+        let mut subscription = Self::default();
+        for field in fields {
+            let Some((key, value)) = field.split_once('=') else {
+                anyhow::bail!("expected key=value, got {field:?}");
+            };
+            match key {
+                "symbols" => {
+                    subscription.symbols = value
+                        .split(',')
+                        .filter(|symbol| !symbol.is_empty())
+                        .map(str::to_owned)
+                        .collect();
+                }
+                "schema" => {
+                    subscription.schema = Some(
+                        value
+                            .parse()
+                            .map_err(|_| anyhow::anyhow!("unknown schema {value:?}"))?,
+                    );
+                }
+                "start" => subscription.start = Some(value.parse()?),
+                "speed" => subscription.speed = Some(value.parse().map_err(anyhow::Error::msg)?),
+                _ => anyhow::bail!("unknown field {key:?}"),
+            }
+        }
+        Ok(subscription)
+    }
+}
+
+impl Display for Reply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "OK"),
+            // Keep the reply on one line
+            Self::Err(reason) => write!(f, "ERR {}", reason.replace(['\r', '\n'], " ")),
+        }
+    }
+}
+
+impl FromStr for Reply {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> anyhow::Result<Self> {
+        match line.split_once(' ') {
+            _ if line == "OK" => Ok(Self::Ok),
+            Some(("ERR", reason)) => Ok(Self::Err(reason.to_owned())),
+            _ if line == "ERR" => Ok(Self::Err(String::new())),
+            _ => anyhow::bail!("unexpected reply {line:?}"),
+        }
+    }
+}
+
+/// Client side: send `subscription` and wait for the server to accept it.
+pub async fn subscribe<S>(stream: &mut S, subscription: &Subscription) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    write_line(stream, &subscription.to_string()).await?;
+    match read_line(stream).await?.parse()? {
+        Reply::Ok => Ok(()),
+        Reply::Err(reason) => anyhow::bail!("subscription rejected: {reason}"),
+    }
+}
+
+/// Read one `\n`-terminated control line without reading past it, so the DBN stream that
+/// follows is left untouched.
+pub async fn read_line<R: AsyncRead + Unpin>(reader: &mut R) -> anyhow::Result<String> {
+    let mut line = Vec::new();
+    loop {
+        let byte = reader.read_u8().await?;
+        if byte == b'\n' {
+            break;
+        }
+        if line.len() == MAX_LINE_LEN {
+            anyhow::bail!("control line longer than {MAX_LINE_LEN} bytes");
+        }
+        line.push(byte);
+    }
+    let line = String::from_utf8(line)?;
+    Ok(line.trim_end_matches('\r').to_owned())
+}
+
+pub async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, line: &str) -> anyhow::Result<()> {
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use databento::dbn::{BboMsg, BidAskPair, FlagSet, Mbp10Msg, Mbp1Msg, Publisher, RecordRef};
+use serde::Serialize;
+
+use crate::orderbook::Market;
+
+/// Book levels published by the vendor in an MBP-10, MBP-1/TBBO or BBO record.
+#[derive(Debug, Clone)]
+pub struct VendorBook {
+    pub ts_recv: u64,
+    pub sequence: u32,
+    pub instrument_id: u32,
+    pub publisher_id: u16,
+    pub flags: FlagSet,
+    /// Whether the record is sampled at an interval (BBO) rather than emitted per event.
+    pub sampled: bool,
+    pub levels: Vec<BidAskPair>,
+}
+
+/// One level where the reconstructed book disagrees with the vendor's.
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelMismatch {
+    pub ts_recv: u64,
+    pub sequence: u32,
+    pub instrument_id: u32,
+    pub publisher_id: u16,
+    pub level: usize,
+    pub ours: Level,
+    pub theirs: Level,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Level {
+    pub bid_px: i64,
+    pub bid_sz: u32,
+    pub bid_ct: u32,
+    pub ask_px: i64,
+    pub ask_sz: u32,
+    pub ask_ct: u32,
+}
+
+/// Compares [`Market`] books with vendor books and tallies the results.
+#[derive(Debug, Default)]
+pub struct Verifier {
+    /// Also compare order counts, which some vendor schemas don't populate.
+    pub compare_counts: bool,
+    stats: HashMap<(u32, u16), VerifyStats>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct VerifyStats {
+    pub compared: u64,
+    pub mismatched: u64,
+    /// Vendor records for books the MBO data never created.
+    pub missing_books: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BookVerifyReport {
+    pub instrument_id: u32,
+    pub publisher_id: u16,
+    #[serde(flatten)]
+    pub stats: VerifyStats,
+}
+
+impl VendorBook {
+    /// Convert an MBP-10, MBP-1/TBBO or BBO record; other records return `None`.
+    pub fn from_record(rec: RecordRef) -> Option<Self> {
+        if let Some(mbp) = rec.get::<Mbp10Msg>() {
+            Some(Self {
+                ts_recv: mbp.ts_recv,
+                sequence: mbp.sequence,
+                instrument_id: mbp.hd.instrument_id,
+                publisher_id: mbp.hd.publisher_id,
+                flags: mbp.flags,
+                sampled: false,
+                levels: mbp.levels.to_vec(),
+            })
+        } else if let Some(mbp) = rec.get::<Mbp1Msg>() {
+            Some(Self {
+                ts_recv: mbp.ts_recv,
+                sequence: mbp.sequence,
+                instrument_id: mbp.hd.instrument_id,
+                publisher_id: mbp.hd.publisher_id,
+                flags: mbp.flags,
+                sampled: false,
+                levels: mbp.levels.to_vec(),
+            })
+        } else {
+            rec.get::<BboMsg>().map(|bbo| Self {
+                ts_recv: bbo.ts_recv,
+                sequence: bbo.sequence,
+                instrument_id: bbo.hd.instrument_id,
+                publisher_id: bbo.hd.publisher_id,
+                flags: bbo.flags,
+                sampled: true,
+                levels: bbo.levels.to_vec(),
+            })
+        }
+    }
+
+    /// Whether an MBO record received at `ts_recv` with `sequence` is reflected in this book.
+    ///
+    /// Per-event records include everything up to their own `(ts_recv, sequence)`; sampled
+    /// records everything received up to the end of their interval.
+    pub fn includes(&self, ts_recv: u64, sequence: u32) -> bool {
+        if self.sampled {
+            ts_recv <= self.ts_recv
+        } else {
+            (ts_recv, sequence) <= (self.ts_recv, self.sequence)
+        }
+    }
+
+    /// Whether the book is complete, i.e. not in the middle of an event.
+    pub fn is_comparable(&self) -> bool {
+        self.sampled || self.flags.is_last()
+    }
+}
+
+impl Verifier {
+    pub fn new(compare_counts: bool) -> Self {
+        Self {
+            compare_counts,
+            ..Self::default()
+        }
+    }
+
+    /// Compare `vendor` with the market's book for the same instrument and publisher.
+    pub fn check(&mut self, market: &Market, vendor: &VendorBook) -> Vec<LevelMismatch> {
+        let stats = self
+            .stats
+            .entry((vendor.instrument_id, vendor.publisher_id))
+            .or_default();
+        stats.compared += 1;
+        let book = Publisher::try_from(vendor.publisher_id)
+            .ok()
+            .and_then(|publisher| market.book(vendor.instrument_id, publisher));
+        let Some(book) = book else {
+            stats.missing_books += 1;
+            return Vec::new();
+        };
+        let mismatches: Vec<LevelMismatch> = book
+            .snapshot(vendor.levels.len())
+            .iter()
+            .zip(&vendor.levels)
+            .enumerate()
+            .filter_map(|(level, (ours, theirs))| {
+                let (ours, theirs) = (Level::new(ours), Level::new(theirs));
+                let equal = if self.compare_counts {
+                    ours == theirs
+                } else {
+                    ours.without_counts() == theirs.without_counts()
+                };
+                (!equal).then_some(LevelMismatch {
+                    ts_recv: vendor.ts_recv,
+                    sequence: vendor.sequence,
+                    instrument_id: vendor.instrument_id,
+                    publisher_id: vendor.publisher_id,
+                    level,
+                    ours,
+                    theirs,
+                })
+            })
+            .collect();
+        if !mismatches.is_empty() {
+            stats.mismatched += 1;
+        }
+        mismatches
+    }
+
+    pub fn report(&self) -> Vec<BookVerifyReport> {
+        let mut books: Vec<BookVerifyReport> = self
+            .stats
+            .iter()
+            .map(|((instrument_id, publisher_id), stats)| BookVerifyReport {
+                instrument_id: *instrument_id,
+                publisher_id: *publisher_id,
+                stats: *stats,
+            })
+            .collect();
+        books.sort_by_key(|book| (book.instrument_id, book.publisher_id));
+        books
+    }
+}
+
+impl Level {
+    fn new(pair: &BidAskPair) -> Self {
+        Self {
+            bid_px: pair.bid_px,
+            bid_sz: pair.bid_sz,
+            bid_ct: pair.bid_ct,
+            ask_px: pair.ask_px,
+            ask_sz: pair.ask_sz,
+            ask_ct: pair.ask_ct,
+        }
+    }
+
+    fn without_counts(self) -> Self {
+        Self {
+            bid_ct: 0,
+            ask_ct: 0,
+            ..self
+        }
+    }
+}
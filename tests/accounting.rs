@@ -0,0 +1,167 @@
+use std::{collections::HashMap, ffi::c_char};
+
+use databento::dbn::{rtype, Action, FlagSet, MboMsg, Publisher, RecordHeader, Side};
+use mbo_orderbook::{
+    accounting::{AccountingConfig, FeeRate, FeeSchedule, MarkPrice, Portfolio},
+    backtest::Fill,
+    orderbook::Market,
+};
+
+const PUBLISHER: Publisher = Publisher::GlbxMdp3Glbx;
+
+#[test]
+fn flipping_a_position_realizes_the_closed_part() {
+    let mut portfolio = Portfolio::new(AccountingConfig::default());
+    portfolio.set_symbol(42, "CLX5");
+    portfolio.apply_fill(&fill(Side::Bid, 100, 3, false));
+    portfolio.apply_fill(&fill(Side::Bid, 103, 1, false));
+    let position = portfolio.position(42).unwrap();
+    assert_eq!(position.qty, 4);
+    assert_close(position.avg_px, 100.75);
+
+    // Selling 6 closes 4 at +2.25 each and opens 2 short at 103
+    portfolio.apply_fill(&fill(Side::Ask, 103, 6, false));
+    let position = portfolio.position(42).unwrap();
+    assert_eq!(position.qty, -2);
+    assert_close(position.avg_px, 103.0);
+    assert_close(position.realized_pnl, 9.0);
+
+    // Buying back 2 at 101 closes the short at +2 each
+    portfolio.apply_fill(&fill(Side::Bid, 101, 2, false));
+    let position = portfolio.position(42).unwrap();
+    assert_eq!(position.qty, 0);
+    assert_close(position.avg_px, 0.0);
+    assert_close(position.realized_pnl, 13.0);
+    assert_eq!(position.volume, 12);
+}
+
+#[test]
+fn open_position_is_marked_to_mid_or_last_trade() {
+    let mut config = AccountingConfig {
+        multipliers: HashMap::from([("CL".to_owned(), 1000.0)]),
+        ..AccountingConfig::default()
+    };
+    let mut market = Market::new();
+    let mut portfolio = Portfolio::new(config.clone());
+    portfolio.set_symbol(42, "CLX5");
+    portfolio.apply_fill(&fill(Side::Bid, 100, 1, false));
+    portfolio.apply_fill(&fill(Side::Bid, 101, 1, false));
+
+    // No book and no trade yet: nothing to mark to
+    let report = portfolio.report(&market);
+    assert_eq!(report.positions[0].mark_px, None);
+    assert_close(report.unrealized_pnl, 0.0);
+
+    // Mid of 101 x 103 is 102: (102 - 100.5) * 2 * 1000
+    market.apply(mbo(1, Action::Add, 1, Side::Bid, 101));
+    market.apply(mbo(2, Action::Add, 2, Side::Ask, 103));
+    portfolio.on_trade(42, 99_000_000_000);
+    let report = portfolio.report(&market);
+    assert_eq!(report.positions[0].mark_px, Some(102.0));
+    assert_close(report.unrealized_pnl, 3000.0);
+    assert_close(report.net_pnl, 3000.0);
+
+    // The last trade at 99: (99 - 100.5) * 2 * 1000
+    config.mark = MarkPrice::LastTrade;
+    let mut portfolio = Portfolio::new(config);
+    portfolio.set_symbol(42, "CLX5");
+    portfolio.apply_fill(&fill(Side::Bid, 100, 1, false));
+    portfolio.apply_fill(&fill(Side::Bid, 101, 1, false));
+    portfolio.on_trade(42, 99_000_000_000);
+    assert_close(portfolio.report(&market).unrealized_pnl, -3000.0);
+}
+
+#[test]
+fn fees_depend_on_publisher_and_liquidity() {
+    let config = AccountingConfig {
+        fees: FeeSchedule {
+            default: FeeRate {
+                maker: 0.1,
+                taker: 0.2,
+            },
+            publishers: HashMap::from([(
+                PUBLISHER,
+                FeeRate {
+                    maker: -0.05,
+                    taker: 0.3,
+                },
+            )]),
+        },
+        ..AccountingConfig::default()
+    };
+    let mut portfolio = Portfolio::new(config);
+    portfolio.apply_fill(&fill(Side::Bid, 100, 4, false));
+    portfolio.apply_fill(&fill(Side::Ask, 100, 2, true));
+    portfolio.apply_fill(&Fill {
+        publisher: Publisher::IfusImpactIfus,
+        ..fill(Side::Ask, 100, 1, true)
+    });
+    portfolio.apply_fill(&Fill {
+        publisher: Publisher::IfusImpactIfus,
+        ..fill(Side::Ask, 100, 1, false)
+    });
+
+    // 4 * -0.05 rebate + 2 * 0.3, then 1 * 0.2 + 1 * 0.1 at the default rate
+    let report = portfolio.report(&Market::new());
+    assert_close(report.fees, 0.7);
+    assert_close(report.net_pnl, -0.7);
+}
+
+#[test]
+fn multiplier_by_symbol_then_futures_root() {
+    let config = AccountingConfig {
+        multipliers: HashMap::from([
+            ("CL".to_owned(), 1000.0),
+            ("CLX5".to_owned(), 500.0),
+            ("Z".to_owned(), 7.0),
+        ]),
+        ..AccountingConfig::default()
+    };
+    assert_eq!(config.multiplier("CLX5"), 500.0);
+    assert_eq!(config.multiplier("CLZ5"), 1000.0);
+    assert_eq!(config.multiplier("CLZ25"), 1000.0);
+    assert_eq!(config.multiplier("CL"), 1000.0);
+    // Not a month code and year
+    assert_eq!(config.multiplier("CLZ2025"), 1.0);
+    assert_eq!(config.multiplier("CLA5"), 1.0);
+    assert_eq!(config.multiplier("Z5"), 1.0);
+    assert_eq!(config.multiplier("ESZ5"), 1.0);
+
+    let mut portfolio = Portfolio::new(config);
+    portfolio.set_symbol(42, "CLF26");
+    assert_eq!(portfolio.multiplier(42), 1000.0);
+    assert_eq!(portfolio.multiplier(43), 1.0);
+}
+
+fn fill(side: Side, px: i64, size: u32, aggressive: bool) -> Fill {
+    Fill {
+        order_id: 1,
+        instrument_id: 42,
+        publisher: PUBLISHER,
+        side,
+        price: px * 1_000_000_000,
+        size,
+        ts: 0,
+        aggressive,
+    }
+}
+
+fn mbo(sequence: u32, action: Action, order_id: u64, side: Side, px: i64) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, PUBLISHER as u16, 42, sequence as u64),
+        order_id,
+        price: px * 1_000_000_000,
+        size: 1,
+        flags: FlagSet::default().set_last(),
+        channel_id: 0,
+        action: action as u8 as c_char,
+        side: side as u8 as c_char,
+        ts_recv: sequence as u64,
+        ts_in_delta: 0,
+        sequence,
+    }
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+}
@@ -0,0 +1,218 @@
+use std::ffi::c_char;
+
+use databento::dbn::{rtype, Action, FlagSet, MboMsg, Publisher, RecordHeader, Side};
+use mbo_orderbook::{
+    backtest::{Backtest, BacktestConfig, Context, Fill, OrderRequest, SimExchange, Strategy},
+    latency::{LatencyConfig, LatencyModel},
+    orderbook::Market,
+};
+
+const PUBLISHER: Publisher = Publisher::GlbxMdp3Glbx;
+
+/// Feeds records through a market and a simulated exchange like `Backtest::process`.
+struct Venue {
+    market: Market,
+    exchange: SimExchange,
+}
+
+impl Venue {
+    fn new(records: &[MboMsg]) -> Self {
+        let mut venue = Self {
+            market: Market::new(),
+            exchange: SimExchange::new(),
+        };
+        for mbo in records {
+            venue.apply(mbo);
+        }
+        venue
+    }
+
+    fn apply(&mut self, mbo: &MboMsg) {
+        self.exchange.on_event(&self.market, mbo);
+        self.market.apply(mbo.clone());
+        self.exchange.after_event(&self.market, mbo);
+    }
+
+    fn submit(&mut self, id: u64, side: Side, px: i64, size: u32, ts: u64) {
+        let request = OrderRequest {
+            id,
+            instrument_id: 42,
+            publisher: PUBLISHER,
+            side,
+            price: Some(px * 1_000_000_000),
+            size,
+        };
+        self.exchange.submit(&self.market, request, ts);
+    }
+
+    fn fills(&self) -> Vec<(i64, u32, bool)> {
+        self.exchange
+            .fills()
+            .iter()
+            .map(|fill| (fill.price / 1_000_000_000, fill.size, fill.aggressive))
+            .collect()
+    }
+}
+
+#[test]
+fn aggressive_order_takes_each_level_once() {
+    let mut venue = Venue::new(&[
+        mbo(1, Action::Add, 1, Side::Ask, 100, 3),
+        mbo(2, Action::Add, 2, Side::Ask, 101, 4),
+    ]);
+    venue.submit(1, Side::Bid, 101, 10, 3);
+    assert_eq!(venue.fills(), [(100, 3, true), (101, 4, true)]);
+    assert_eq!(venue.exchange.order(1).unwrap().remaining(), 3);
+
+    // The rest keeps resting: the levels it already took don't fill it again
+    venue.apply(&mbo(4, Action::Add, 3, Side::Bid, 90, 1));
+    venue.apply(&mbo(5, Action::Modify, 3, Side::Bid, 90, 2));
+    assert_eq!(venue.fills().len(), 2);
+}
+
+#[test]
+fn crossing_level_fills_once() {
+    let mut venue = Venue::new(&[mbo(1, Action::Add, 1, Side::Ask, 102, 1)]);
+    venue.submit(1, Side::Bid, 100, 10, 2);
+    assert!(venue.fills().is_empty());
+
+    venue.apply(&mbo(3, Action::Add, 2, Side::Ask, 100, 5));
+    assert_eq!(venue.fills(), [(100, 5, false)]);
+
+    // Later events leave the crossing level as it was
+    venue.apply(&mbo(4, Action::Add, 3, Side::Bid, 90, 1));
+    venue.apply(&mbo(5, Action::Cancel, 3, Side::Bid, 90, 1));
+    assert_eq!(venue.fills(), [(100, 5, false)]);
+
+    // New size at the level is new liquidity
+    venue.apply(&mbo(6, Action::Add, 4, Side::Ask, 100, 2));
+    assert_eq!(venue.fills(), [(100, 5, false), (100, 2, false)]);
+}
+
+#[test]
+fn trade_through_price_fills_resting_order() {
+    let mut venue = Venue::new(&[
+        mbo(1, Action::Add, 1, Side::Bid, 99, 4),
+        mbo(2, Action::Add, 2, Side::Bid, 98, 4),
+    ]);
+    venue.submit(1, Side::Bid, 99, 3, 3);
+    assert_eq!(venue.exchange.order(1).unwrap().queue_ahead, 4);
+
+    // A sell sweeping down to 98 traded through our price, ahead of the queue at 99
+    venue.apply(&mbo(4, Action::Trade, 0, Side::Ask, 98, 2));
+    assert_eq!(venue.fills(), [(99, 2, false)]);
+    assert_eq!(venue.exchange.order(1).unwrap().remaining(), 1);
+}
+
+#[test]
+fn trade_at_price_fills_only_behind_queue() {
+    let mut venue = Venue::new(&[mbo(1, Action::Add, 1, Side::Bid, 99, 4)]);
+    venue.submit(1, Side::Bid, 99, 3, 2);
+
+    venue.apply(&mbo(3, Action::Trade, 0, Side::Ask, 99, 3));
+    assert!(venue.fills().is_empty());
+    venue.apply(&mbo(4, Action::Trade, 0, Side::Ask, 99, 2));
+    assert_eq!(venue.fills(), [(99, 1, false)]);
+}
+
+#[test]
+fn order_without_side_is_rejected() {
+    let mut venue = Venue::new(&[mbo(1, Action::Add, 1, Side::Ask, 100, 3)]);
+    venue.submit(1, Side::None, 100, 3, 2);
+    assert!(venue.fills().is_empty());
+    assert!(venue.exchange.order(1).is_none());
+}
+
+/// Rests a bid on the first book update, then cancels it on the next one.
+#[derive(Default)]
+struct CancelAfterUpdate {
+    order_id: Option<u64>,
+    cancel_sent: bool,
+    fills: Vec<Fill>,
+}
+
+impl Strategy for CancelAfterUpdate {
+    fn on_book_update(&mut self, ctx: &mut Context<'_>, _mbo: &MboMsg) {
+        match self.order_id {
+            None => {
+                let id = ctx.submit_limit(42, PUBLISHER, Side::Bid, 99_000_000_000, 3);
+                self.order_id = Some(id);
+            }
+            Some(id) if !self.cancel_sent => {
+                ctx.cancel(id);
+                self.cancel_sent = true;
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn on_fill(&mut self, _ctx: &mut Context<'_>, fill: &Fill) {
+        self.fills.push(fill.clone());
+    }
+}
+
+#[test]
+fn cancel_racing_a_fill_arrives_too_late() {
+    let config = BacktestConfig {
+        latency: LatencyConfig {
+            order_entry: LatencyModel::Constant(1_000),
+            market_data: LatencyModel::Constant(0),
+        },
+        ..Default::default()
+    };
+    let mut backtest = Backtest::with_config(CancelAfterUpdate::default(), config);
+    // The bid reaches the exchange at 1_000, the cancel sent at 2_000 arrives at 3_000,
+    // after the trade through its price at 2_500
+    backtest.process(&mbo_at(0, Action::Add, 1, Side::Ask, 101, 5));
+    backtest.process(&mbo_at(2_000, Action::Add, 2, Side::Ask, 102, 5));
+    backtest.process(&mbo_at(2_500, Action::Trade, 0, Side::Ask, 98, 5));
+    backtest.process(&mbo_at(4_000, Action::Add, 3, Side::Ask, 103, 5));
+
+    let fills: Vec<_> = backtest
+        .report()
+        .fills
+        .iter()
+        .map(|fill| (fill.price, fill.size, fill.ts))
+        .collect();
+    assert_eq!(fills, [(99_000_000_000, 3, 2_500)]);
+    assert_eq!(backtest.strategy().fills.len(), 1);
+    assert!(backtest.exchange().open_orders().next().is_none());
+}
+
+#[test]
+fn cancel_arriving_first_prevents_the_fill() {
+    let config = BacktestConfig {
+        latency: LatencyConfig {
+            order_entry: LatencyModel::Constant(100),
+            market_data: LatencyModel::Constant(0),
+        },
+        ..Default::default()
+    };
+    let mut backtest = Backtest::with_config(CancelAfterUpdate::default(), config);
+    backtest.process(&mbo_at(0, Action::Add, 1, Side::Ask, 101, 5));
+    backtest.process(&mbo_at(2_000, Action::Add, 2, Side::Ask, 102, 5));
+    backtest.process(&mbo_at(2_500, Action::Trade, 0, Side::Ask, 98, 5));
+
+    assert!(backtest.report().fills.is_empty());
+    assert!(backtest.strategy().fills.is_empty());
+}
+
+fn mbo(sequence: u32, action: Action, order_id: u64, side: Side, px: i64, size: u32) -> MboMsg {
+    mbo_at(sequence as u64, action, order_id, side, px, size)
+}
+
+fn mbo_at(ts: u64, action: Action, order_id: u64, side: Side, px: i64, size: u32) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, PUBLISHER as u16, 42, ts),
+        order_id,
+        price: px * 1_000_000_000,
+        size,
+        flags: FlagSet::default().set_last(),
+        channel_id: 0,
+        action: action as u8 as c_char,
+        side: side as u8 as c_char,
+        ts_recv: ts,
+        ts_in_delta: 0,
+        sequence: ts as u32,
+    }
+}
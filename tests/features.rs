@@ -0,0 +1,142 @@
+use std::ffi::c_char;
+
+use databento::dbn::{rtype, Action, FlagSet, MboMsg, Publisher, RecordHeader, Side};
+use mbo_orderbook::{
+    features::{Clock, FeatureConfig, FeatureRow, FeatureTracker},
+    orderbook::Market,
+};
+
+/// Applies records to a market and collects the rows the tracker emits.
+struct Replay {
+    market: Market,
+    tracker: FeatureTracker,
+    rows: Vec<FeatureRow>,
+}
+
+impl Replay {
+    fn new(clock: Clock) -> Self {
+        Self {
+            market: Market::new(),
+            tracker: FeatureTracker::new(FeatureConfig {
+                depths: vec![1, 2],
+                clock,
+            }),
+            rows: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, mbo: MboMsg) -> &[FeatureRow] {
+        self.market.apply(mbo.clone());
+        let start = self.rows.len();
+        let rows = self.tracker.update(&self.market, &mbo);
+        self.rows.extend(rows);
+        &self.rows[start..]
+    }
+}
+
+#[test]
+fn ofi_per_event() {
+    let mut replay = Replay::new(Clock::Event);
+    let mut ofi = |mbo| {
+        let rows = replay.apply(mbo);
+        assert_eq!(rows.len(), 1);
+        rows[0].ofi.clone()
+    };
+    // A new bid level is flow into the bid, a new ask level flow into the ask
+    assert_eq!(ofi(mbo(1, Action::Add, 1, Side::Bid, 100, 5)), [5.0, 5.0]);
+    assert_eq!(ofi(mbo(2, Action::Add, 2, Side::Ask, 102, 4)), [-4.0, -4.0]);
+    // An improved bid counts its full size at the top; the old best moves to level 2
+    assert_eq!(ofi(mbo(3, Action::Add, 3, Side::Bid, 101, 2)), [2.0, 7.0]);
+    // Size leaving the ask at the same price is positive flow
+    assert_eq!(
+        ofi(mbo(4, Action::Cancel, 2, Side::Ask, 102, 1)),
+        [1.0, 1.0]
+    );
+    // The bid backing off loses its previous size at each level
+    assert_eq!(
+        ofi(mbo(5, Action::Cancel, 3, Side::Bid, 101, 2)),
+        [-2.0, -7.0]
+    );
+}
+
+#[test]
+fn signed_volume_and_counts() {
+    let mut replay = Replay::new(Clock::Volume { volume: 5 });
+    replay.apply(mbo(1, Action::Add, 1, Side::Bid, 100, 5));
+    replay.apply(mbo(2, Action::Add, 2, Side::Ask, 102, 4));
+    replay.apply(mbo(3, Action::Cancel, 1, Side::Bid, 100, 1));
+    assert!(replay
+        .apply(mbo(4, Action::Trade, 0, Side::Bid, 102, 3))
+        .is_empty());
+
+    // A buy of 3 and a sell of 2 reach the volume bar
+    let rows = replay.apply(mbo(5, Action::Trade, 0, Side::Ask, 100, 2));
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!(row.signed_volume, 1);
+    assert_eq!(row.trade_volume, 5);
+    assert_eq!(row.trades, 2);
+    assert_eq!((row.bid_adds, row.ask_adds), (1, 1));
+    assert_eq!((row.bid_cancels, row.ask_cancels), (1, 0));
+    assert_eq!(row.cancel_to_trade(), Some(0.5));
+    assert_eq!(row.mid, Some(101.0));
+    assert_eq!((row.ts_recv, row.duration_ns), (5, 4));
+
+    // Counters start over for the next bar
+    assert!(replay
+        .apply(mbo(6, Action::Trade, 0, Side::Ask, 100, 4))
+        .is_empty());
+    let rows = replay.apply(mbo(7, Action::Trade, 0, Side::Bid, 102, 1));
+    assert_eq!((rows[0].signed_volume, rows[0].trade_volume), (-3, 5));
+}
+
+#[test]
+fn time_clock_emits_at_interval_ends() {
+    let mut replay = Replay::new(Clock::Time { interval_ns: 10 });
+    assert!(replay
+        .apply(mbo(3, Action::Add, 1, Side::Bid, 100, 5))
+        .is_empty());
+    assert!(replay
+        .apply(mbo(5, Action::Add, 2, Side::Ask, 102, 4))
+        .is_empty());
+
+    let rows = replay.apply(mbo(12, Action::Add, 3, Side::Bid, 99, 1));
+    assert_eq!(rows.len(), 1);
+    assert_eq!((rows[0].ts_recv, rows[0].duration_ns), (10, 7));
+    assert_eq!(rows[0].ofi, [1.0, 1.0]);
+
+    // The empty interval [20, 30) is skipped
+    let rows = replay.apply(mbo(35, Action::Cancel, 3, Side::Bid, 99, 1));
+    assert_eq!((rows[0].ts_recv, rows[0].duration_ns), (20, 10));
+    assert_eq!(rows[0].bid_adds, 1);
+
+    let rows = replay.tracker.flush();
+    assert_eq!((rows[0].ts_recv, rows[0].duration_ns), (35, 15));
+    assert_eq!(rows[0].bid_cancels, 1);
+}
+
+#[test]
+fn zero_time_interval_is_one_nanosecond() {
+    let mut replay = Replay::new(Clock::Time { interval_ns: 0 });
+    assert!(replay
+        .apply(mbo(3, Action::Add, 1, Side::Bid, 100, 5))
+        .is_empty());
+    let rows = replay.apply(mbo(4, Action::Add, 2, Side::Ask, 102, 4));
+    assert_eq!((rows[0].ts_recv, rows[0].duration_ns), (4, 1));
+}
+
+fn mbo(ts: u64, action: Action, order_id: u64, side: Side, px: i64, size: u32) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, Publisher::GlbxMdp3Glbx as u16, 42, ts),
+        order_id,
+        price: px * 1_000_000_000,
+        size,
+        flags: FlagSet::default().set_last(),
+        channel_id: 0,
+        action: action as u8 as c_char,
+        side: side as u8 as c_char,
+        ts_recv: ts,
+        ts_in_delta: 0,
+        sequence: ts as u32,
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    env,
+    ffi::c_char,
+    fs::{self, File},
+    path::Path,
+    process::Command,
+};
+
+use databento::dbn::{
+    encode::{DbnEncoder, EncodeRecord},
+    rtype, Action, FlagSet, MboMsg, MetadataBuilder, RecordHeader, SType, Schema, Side,
+};
+
+const HEADER: &str = "include/mbo_orderbook.h";
+
+#[test]
+fn header_is_up_to_date() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(root)
+        .with_config(config)
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+    if fs::read_to_string(root.join(HEADER)).ok().as_deref() != Some(generated.as_str()) {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("mbo_orderbook.h");
+        fs::write(&path, generated).unwrap();
+        panic!(
+            "{HEADER} is out of date; the generated header is at {}, or run \
+             `cbindgen --config cbindgen.toml --output {HEADER}`",
+            path.display()
+        );
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn c_example() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let dbn = tmp.join("ffi_example.mbo.dbn");
+    write_dbn(&dbn);
+
+    // The test binary lives in target/<profile>/deps, next to the cdylib
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let lib_dir = [deps.clone(), deps.parent().unwrap().to_path_buf()]
+        .into_iter()
+        .find(|dir| {
+            dir.join(format!(
+                "{}mbo_orderbook{}",
+                env::consts::DLL_PREFIX,
+                env::consts::DLL_SUFFIX
+            ))
+            .exists()
+        })
+        .expect("cdylib not built");
+    let exe = tmp.join("ffi_book");
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .arg(root.join("tests/ffi/book.c"))
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(format!("-I{}", root.join("include").display()))
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lmbo_orderbook")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling tests/ffi/book.c failed");
+
+    // Cargo's library path may hold a stale cdylib from another build
+    let output = Command::new(&exe)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .args([dbn.as_os_str(), "42".as_ref(), "1".as_ref(), "3".as_ref()])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "applied 6 records\n\
+         bid: 7 @ 100000000000 (2 orders)\n\
+         ask: 1 @ 101000000000 (1 orders)\n\
+         level 0: 7 @ 100000000000 | 1 @ 101000000000\n\
+         level 1: 2 @ 99000000000 | 0 @ 9223372036854775807\n\
+         order 3: B 3 @ 100000000000, 4 ahead\n"
+    );
+}
+
+fn write_dbn(path: &Path) {
+    let metadata = MetadataBuilder::new()
+        .dataset("GLBX.MDP3".to_owned())
+        .schema(Some(Schema::Mbo))
+        .start(0)
+        .stype_in(Some(SType::InstrumentId))
+        .stype_out(SType::InstrumentId)
+        .build();
+    let mut encoder = DbnEncoder::new(File::create(path).unwrap(), &metadata).unwrap();
+    let adds = [
+        (1, Side::Bid, 100, 4),
+        (2, Side::Bid, 99, 2),
+        (3, Side::Bid, 100, 3),
+        (4, Side::Ask, 101, 1),
+        (5, Side::Ask, 102, 6),
+    ];
+    for (seq, (order_id, side, px, size)) in adds.into_iter().enumerate() {
+        encoder
+            .encode_record(&mbo(seq as u32, Action::Add, order_id, side, px, size))
+            .unwrap();
+    }
+    encoder
+        .encode_record(&mbo(5, Action::Cancel, 5, Side::Ask, 102, 6))
+        .unwrap();
+}
+
+fn mbo(sequence: u32, action: Action, order_id: u64, side: Side, px: i64, size: u32) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, 1, 42, sequence as u64),
+        order_id,
+        price: px * 1_000_000_000,
+        size,
+        flags: FlagSet::default().set_last(),
+        channel_id: 0,
+        action: action as u8 as c_char,
+        side: side as u8 as c_char,
+        ts_recv: sequence as u64,
+        ts_in_delta: 0,
+        sequence,
+    }
+}
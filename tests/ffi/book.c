@@ -0,0 +1,101 @@
+/*
+ * Replay a DBN MBO file through the Rust order book and print one book.
+ *
+ *   cargo build --release
+ *   cc tests/ffi/book.c -Iinclude -Ltarget/release -lmbo_orderbook -o book
+ *   LD_LIBRARY_PATH=target/release ./book CLX5_mbo.dbn 42 1 [ORDER_ID]
+ */
+#include <inttypes.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "mbo_orderbook.h"
+
+#define LEVELS 5
+
+static void print_level(const char *name, MboPriceLevel level) {
+    if (level.price == MBO_UNDEF_PRICE) {
+        printf("%s: none\n", name);
+    } else {
+        printf("%s: %" PRIu32 " @ %" PRId64 " (%" PRIu32 " orders)\n", name, level.size,
+               level.price, level.count);
+    }
+}
+
+static unsigned char *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    *len = (size_t)ftell(file);
+    fseek(file, 0, SEEK_SET);
+    unsigned char *data = malloc(*len);
+    if (data && fread(data, 1, *len, file) != *len) {
+        free(data);
+        data = NULL;
+    }
+    fclose(file);
+    return data;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 4) {
+        fprintf(stderr, "usage: %s DBN_FILE INSTRUMENT_ID PUBLISHER_ID [ORDER_ID]\n", argv[0]);
+        return 2;
+    }
+    uint32_t instrument_id = (uint32_t)strtoul(argv[2], NULL, 10);
+    uint16_t publisher_id = (uint16_t)strtoul(argv[3], NULL, 10);
+
+    size_t len = 0;
+    unsigned char *data = read_file(argv[1], &len);
+    if (!data) {
+        fprintf(stderr, "can't read %s\n", argv[1]);
+        return 1;
+    }
+
+    MboMarket *market = mbo_market_new();
+    size_t applied = 0;
+    MboStatus status = mbo_market_apply(market, data, len, &applied);
+    free(data);
+    if (status != MBO_STATUS_OK) {
+        fprintf(stderr, "apply failed: %d\n", (int)status);
+        mbo_market_free(market);
+        return 1;
+    }
+    printf("applied %zu records\n", applied);
+
+    MboPriceLevel bid, ask;
+    if (mbo_market_bbo(market, instrument_id, publisher_id, &bid, &ask) == MBO_STATUS_OK) {
+        print_level("bid", bid);
+        print_level("ask", ask);
+    } else {
+        printf("no book\n");
+    }
+
+    MboBidAskPair levels[LEVELS];
+    size_t filled = 0;
+    mbo_market_depth(market, instrument_id, publisher_id, levels, LEVELS, &filled);
+    for (size_t i = 0; i < filled; i++) {
+        printf("level %zu: %" PRIu32 " @ %" PRId64 " | %" PRIu32 " @ %" PRId64 "\n", i,
+               levels[i].bid_sz, levels[i].bid_px, levels[i].ask_sz, levels[i].ask_px);
+    }
+
+    if (argc > 4) {
+        uint64_t order_id = strtoull(argv[4], NULL, 10);
+        MboOrder order;
+        uint32_t queue_pos = 0;
+        if (mbo_market_order(market, instrument_id, publisher_id, order_id, &order) ==
+                MBO_STATUS_OK &&
+            mbo_market_queue_pos(market, instrument_id, publisher_id, order_id, &queue_pos) ==
+                MBO_STATUS_OK) {
+            printf("order %" PRIu64 ": %c %" PRIu32 " @ %" PRId64 ", %" PRIu32 " ahead\n",
+                   order.order_id, order.side, order.size, order.price, queue_pos);
+        } else {
+            printf("order %" PRIu64 ": not found\n", order_id);
+        }
+    }
+
+    mbo_market_free(market);
+    return 0;
+}
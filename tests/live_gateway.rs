@@ -0,0 +1,215 @@
+use std::{
+    ffi::c_char,
+    fs::File,
+    net::TcpListener,
+    num::NonZeroU64,
+    path::Path,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use databento::{
+    dbn::{
+        encode::{DbnEncoder, EncodeRecord},
+        rtype, Action, ErrorMsg, FlagSet, MappingInterval, MboMsg, MetadataBuilder, RecordHeader,
+        SType, Schema, Side, SymbolMapping,
+    },
+    live::Subscription,
+    LiveClient,
+};
+use time::macros::date;
+
+const KEY: &str = "db-0123456789abcdefghijklmnopqrs";
+
+/// An `mbo-streamer` speaking the Live protocol, killed on drop.
+struct Gateway {
+    child: Child,
+    port: u16,
+}
+
+impl Gateway {
+    fn spawn(name: &str, mode: &str) -> Self {
+        let dbn = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.mbo.dbn"));
+        write_dbn(&dbn);
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let child = Command::new(env!("CARGO_BIN_EXE_mbo-streamer"))
+            .arg(&dbn)
+            .args(["--bind", &format!("127.0.0.1:{port}")])
+            .args(["--mode", mode])
+            .args(["--live-key", KEY])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        Self { child, port }
+    }
+
+    async fn connect(&self, key: &str, send_ts_out: bool) -> databento::Result<LiveClient> {
+        // The streamer may still be starting up
+        for _ in 0..50 {
+            let client = LiveClient::builder()
+                .addr(("127.0.0.1", self.port))
+                .await?
+                .key(key)?
+                .dataset("GLBX.MDP3")
+                .send_ts_out(send_ts_out)
+                .build()
+                .await;
+            match client {
+                Err(databento::Error::Io(err))
+                    if err.kind() == std::io::ErrorKind::ConnectionRefused =>
+                {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                client => return client,
+            }
+        }
+        panic!("mbo-streamer didn't start listening");
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[tokio::test]
+async fn live_client_replays_subscriptions() {
+    for mode in ["buffered", "streaming"] {
+        let gateway = Gateway::spawn(&format!("live_{mode}"), mode);
+        let mut client = gateway.connect(KEY, true).await.unwrap();
+        client
+            .subscribe(
+                Subscription::builder()
+                    .schema(Schema::Mbo)
+                    .symbols("CLZ5")
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let metadata = client.start().await.unwrap();
+        assert_eq!(metadata.dataset, "GLBX.MDP3");
+        assert_eq!(metadata.symbols, ["CLZ5"]);
+        assert!(metadata.ts_out);
+
+        let mut order_ids = Vec::new();
+        while let Some(record) = client.next_record().await.unwrap() {
+            let mbo = record.get::<MboMsg>().unwrap();
+            assert_eq!(mbo.hd.instrument_id, 43);
+            order_ids.push(mbo.order_id);
+        }
+        assert_eq!(order_ids, (2..=20).step_by(2).collect::<Vec<_>>(), "{mode}");
+    }
+}
+
+#[tokio::test]
+async fn live_client_unknown_symbol_gets_error_record() {
+    let gateway = Gateway::spawn("live_unknown", "buffered");
+    let mut client = gateway.connect(KEY, false).await.unwrap();
+    client
+        .subscribe(
+            Subscription::builder()
+                .schema(Schema::Mbo)
+                .symbols("ESZ5")
+                .build(),
+        )
+        .await
+        .unwrap();
+    client
+        .subscribe(
+            Subscription::builder()
+                .schema(Schema::Mbo)
+                .stype_in(SType::InstrumentId)
+                .symbols(42)
+                .build(),
+        )
+        .await
+        .unwrap();
+    client.start().await.unwrap();
+
+    let record = client.next_record().await.unwrap().unwrap();
+    let error = record.get::<ErrorMsg>().unwrap();
+    assert!(error.err().unwrap().contains("ESZ5"), "{error:?}");
+    let mut count = 0;
+    while let Some(record) = client.next_record().await.unwrap() {
+        assert_eq!(record.get::<MboMsg>().unwrap().hd.instrument_id, 42);
+        count += 1;
+    }
+    assert_eq!(count, 10);
+}
+
+#[tokio::test]
+async fn live_client_snapshot_subscription_is_rejected() {
+    let gateway = Gateway::spawn("live_snapshot", "buffered");
+    let mut client = gateway.connect(KEY, false).await.unwrap();
+    client
+        .subscribe(
+            Subscription::builder()
+                .schema(Schema::Mbo)
+                .symbols("CLZ5")
+                .use_snapshot()
+                .build(),
+        )
+        .await
+        .unwrap();
+    let metadata = client.start().await.unwrap();
+    // Nothing was subscribed, so nothing is mapped
+    assert!(metadata.symbols.is_empty());
+    assert!(metadata.mappings.is_empty());
+
+    let record = client.next_record().await.unwrap().unwrap();
+    let error = record.get::<ErrorMsg>().unwrap();
+    assert!(error.err().unwrap().contains("snapshot"), "{error:?}");
+    assert!(client.next_record().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn live_client_wrong_key_is_rejected() {
+    let gateway = Gateway::spawn("live_wrong_key", "buffered");
+    let wrong_key = KEY.replace("db-0", "db-1");
+    let err = gateway.connect(&wrong_key, false).await.unwrap_err();
+    assert!(err.to_string().contains("Authentication failed"), "{err}");
+}
+
+fn write_dbn(path: &Path) {
+    let mapping = |raw_symbol: &str, id: &str| SymbolMapping {
+        raw_symbol: raw_symbol.to_owned(),
+        intervals: vec![MappingInterval {
+            start_date: date!(2025 - 10 - 01),
+            end_date: date!(2025 - 10 - 02),
+            symbol: id.to_owned(),
+        }],
+    };
+    let mut metadata = MetadataBuilder::new()
+        .dataset("GLBX.MDP3".to_owned())
+        .schema(Some(Schema::Mbo))
+        .start(1_000)
+        .end(NonZeroU64::new(100_000))
+        .stype_in(Some(SType::RawSymbol))
+        .stype_out(SType::InstrumentId)
+        .symbols(vec!["CLX5".to_owned(), "CLZ5".to_owned()])
+        .build();
+    metadata.mappings = vec![mapping("CLX5", "42"), mapping("CLZ5", "43")];
+    let mut encoder = DbnEncoder::new(File::create(path).unwrap(), &metadata).unwrap();
+    for i in 0..20u64 {
+        let mbo = MboMsg {
+            hd: RecordHeader::new::<MboMsg>(rtype::MBO, 1, 42 + i as u32 % 2, 1_000 + i * 1_000),
+            order_id: i + 1,
+            price: 100_000_000_000,
+            size: 1,
+            flags: FlagSet::default().set_last(),
+            channel_id: 0,
+            action: Action::Add as u8 as c_char,
+            side: Side::Bid as u8 as c_char,
+            ts_recv: 1_000 + i * 1_000,
+            ts_in_delta: 0,
+            sequence: i as u32,
+        };
+        encoder.encode_record(&mbo).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use std::ffi::c_char;
+
+use databento::dbn::{rtype, Action, FlagSet, MboMsg, Publisher, RecordHeader, Side};
+use mbo_orderbook::{order_stats::OrderStats, orderbook::Market};
+
+#[test]
+fn replay_aggregates() {
+    let mut market = Market::new();
+    let mut stats = OrderStats::new();
+    for mbo in [
+        mbo(10, Action::Add, 1, 100, 5),
+        // Behind order 1 in the queue
+        mbo(20, Action::Add, 2, 100, 3),
+        // One and two levels below the best bid
+        mbo(30, Action::Add, 3, 99, 2),
+        mbo(35, Action::Add, 4, 97, 1),
+        // Order 1 fills in full, putting order 2 at the front
+        mbo(40, Action::Fill, 1, 100, 5),
+        mbo(40, Action::Cancel, 1, 100, 5),
+        mbo(50, Action::Cancel, 3, 99, 2),
+        // Order 2 fills in part and the rest is cancelled
+        mbo(60, Action::Fill, 2, 100, 1),
+        mbo(70, Action::Cancel, 2, 100, 3),
+    ] {
+        stats.apply(&mut market, &mbo);
+    }
+
+    let report = stats.report();
+    // Order 4 is still resting
+    assert_eq!(report.open_orders, 1);
+    assert_eq!(report.books.len(), 1);
+    let book = &report.books[0];
+    assert_eq!((book.instrument_id, book.publisher), (42, PUBLISHER));
+    assert_eq!(book.orders_added, 4);
+    assert_eq!(
+        (book.fully_filled, book.partially_filled, book.cancelled),
+        (1, 1, 1)
+    );
+
+    // Orders 1 and 2 lived 30 and 50, order 3 lived 20
+    assert_eq!(book.filled_lifetime.count, 2);
+    assert_eq!(book.filled_lifetime.mean_ns, 40.0);
+    assert_eq!(book.filled_lifetime.max_ns, 50);
+    assert_eq!(book.cancelled_lifetime.count, 1);
+    assert_eq!(book.cancelled_lifetime.max_ns, 20);
+
+    // Order 1 led from 10 to 40, order 3 from 30 to 50, order 2 from 40 to 70
+    assert_eq!(book.time_at_head.count, 3);
+    assert_eq!(book.time_at_head.p50_ns, 30);
+    assert_eq!(book.time_at_head.max_ns, 30);
+    assert!((book.time_at_head.mean_ns - 80.0 / 3.0).abs() < 1e-9);
+
+    let by_distance: Vec<_> = book
+        .by_distance
+        .iter()
+        .map(|row| {
+            (
+                row.levels_from_bbo,
+                row.orders,
+                row.cancelled,
+                row.cancel_rate,
+            )
+        })
+        .collect();
+    assert_eq!(by_distance, [(0, 2, 0, 0.0), (1, 1, 1, 1.0)]);
+
+    let by_queue: Vec<_> = book
+        .by_queue_position
+        .iter()
+        .map(|row| {
+            (
+                row.orders_ahead_min,
+                row.orders_ahead_max,
+                row.orders,
+                row.filled,
+                row.fill_probability,
+            )
+        })
+        .collect();
+    assert_eq!(by_queue, [(0, 0, 2, 1, 0.5), (1, 1, 1, 1, 1.0)]);
+
+    assert_eq!(book.modifies.max, 0);
+    assert_eq!(book.modifies.histogram.get(&0), Some(&3));
+}
+
+#[test]
+fn snapshot_orders_are_not_counted() {
+    let mut market = Market::new();
+    let mut stats = OrderStats::new();
+    let mut snapshot = mbo(10, Action::Add, 1, 100, 5);
+    snapshot.flags = FlagSet::default().set_snapshot().set_last();
+    stats.apply(&mut market, &snapshot);
+    stats.apply(&mut market, &mbo(20, Action::Add, 2, 100, 1));
+    stats.apply(&mut market, &mbo(30, Action::Cancel, 1, 100, 5));
+
+    let report = stats.report();
+    assert_eq!(report.open_orders, 1);
+    let book = &report.books[0];
+    assert_eq!((book.orders_added, book.cancelled), (1, 0));
+    assert!(book.by_distance.is_empty());
+}
+
+const PUBLISHER: Publisher = Publisher::GlbxMdp3Glbx;
+
+fn mbo(ts: u64, action: Action, order_id: u64, px: i64, size: u32) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, PUBLISHER as u16, 42, ts),
+        order_id,
+        price: px * 1_000_000_000,
+        size,
+        flags: FlagSet::default().set_last(),
+        channel_id: 0,
+        action: action as u8 as c_char,
+        side: Side::Bid as u8 as c_char,
+        ts_recv: ts,
+        ts_in_delta: 0,
+        sequence: ts as u32,
+    }
+}
@@ -0,0 +1,54 @@
+use std::ffi::c_char;
+
+use databento::dbn::{rtype, Action, FlagSet, MboMsg, Publisher, RecordHeader, Side};
+use mbo_orderbook::orderbook::Market;
+
+const PUBLISHER: Publisher = Publisher::GlbxMdp3Glbx;
+
+#[test]
+fn incremental_state_hash_matches_full_hash() {
+    let mut hashed = Market::new().with_state_hash();
+    let mut plain = Market::new();
+    for mbo in [
+        mbo(Action::Add, 1, Side::Bid, 100, 5),
+        mbo(Action::Add, 2, Side::Bid, 100, 3),
+        mbo(Action::Add, 3, Side::Ask, 102, 4),
+        // Loses priority and moves to another level
+        mbo(Action::Modify, 1, Side::Bid, 101, 5),
+        mbo(Action::Cancel, 2, Side::Bid, 100, 1),
+        mbo(Action::Fill, 3, Side::Ask, 102, 4),
+        mbo(Action::Cancel, 3, Side::Ask, 102, 4),
+    ] {
+        hashed.apply(mbo.clone());
+        plain.apply(mbo);
+        let book = hashed.book(42, PUBLISHER).unwrap();
+        assert_eq!(book.validate(), Ok(()));
+        assert_eq!(
+            book.state_hash(),
+            plain.book(42, PUBLISHER).unwrap().state_hash()
+        );
+    }
+    assert_ne!(hashed.book(42, PUBLISHER).unwrap().state_hash(), 0);
+
+    let clear = mbo(Action::Clear, 0, Side::None, 0, 0);
+    hashed.apply(clear.clone());
+    plain.apply(clear);
+    assert_eq!(hashed.book(42, PUBLISHER).unwrap().state_hash(), 0);
+    assert_eq!(plain.book(42, PUBLISHER).unwrap().state_hash(), 0);
+}
+
+fn mbo(action: Action, order_id: u64, side: Side, px: i64, size: u32) -> MboMsg {
+    MboMsg {
+        hd: RecordHeader::new::<MboMsg>(rtype::MBO, PUBLISHER as u16, 42, 0),
+        order_id,
+        price: px * 1_000_000_000,
+        size,
+        flags: FlagSet::default().set_last(),
+        channel_id: 0,
+        action: action as u8 as c_char,
+        side: side as u8 as c_char,
+        ts_recv: 0,
+        ts_in_delta: 0,
+        sequence: 0,
+    }
+}
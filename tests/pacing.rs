@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use mbo_orderbook::pacing::{Pacer, Speed};
+use tokio::time::Instant;
+
+#[test]
+fn parse_speed() {
+    assert_eq!("max".parse(), Ok(Speed::Max));
+    assert_eq!("0.5x".parse(), Ok(Speed::Factor(0.5)));
+    assert_eq!("10".parse(), Ok(Speed::Factor(10.0)));
+    assert_eq!("1e-6x".parse(), Ok(Speed::Factor(1e-6)));
+    for invalid in ["0x", "-2x", "infx", "NaN", "1e-300x", "fast"] {
+        assert!(invalid.parse::<Speed>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn tiny_factor_saturates() {
+    let mut pacer = Pacer::new(Speed::Factor(1e-300), None);
+    assert_eq!(pacer.deadline(0), None);
+    let deadline = pacer.deadline(1_000_000_000).unwrap();
+    assert!(deadline > Instant::now() + Duration::from_secs(86400 * 365));
+
+    // Quiet periods are still cut to the longest sleep
+    let max_sleep = Duration::from_millis(100);
+    let mut pacer = Pacer::new(Speed::Factor(1e-300), Some(max_sleep));
+    pacer.deadline(0);
+    let deadline = pacer.deadline(1_000_000_000).unwrap();
+    assert!(deadline <= Instant::now() + max_sleep);
+}